@@ -0,0 +1,198 @@
+//! Forwards a stream of game packets from a backend socket to a client socket through the real path
+//! of the proxy: ``read_frame`` of the backend read task, ``queue_frame`` of the client connection and
+//! the write task with its ``BatchWriter``. Frames are read into a new vector per packet like before
+//! and into slices of the ``BufferPool``, the time and the allocations of all tasks are counted.
+//!
+//! Run with ``cargo bench --bench forwarding``
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+// the crate has no library target, it is compiled into the benchmark from its source.
+// Its warnings are reported for the binary already
+#[path = "../src/main.rs"]
+#[allow(warnings, clippy::all)]
+mod crust;
+
+// the modules of the crate refer to each other through the crate root
+use crust::{auth, chat, haproxy, server, util, version};
+
+use server::{
+    buffer_pool::BufferPool,
+    compression,
+    limits::MAX_FRAME_SIZE,
+    network::Stream,
+    packets::{read_frame, ProtocolState},
+    proxy_handler::{self, ConnectionHandle},
+    ProxyConfig, ProxyServer,
+};
+use util::{IOResult, VarInt};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const PACKETS: usize = 200_000;
+
+/// Sizes of a stream of game packets, mostly small movement packets with a few chunks in between
+fn packet_sizes() -> Vec<usize> {
+    (0..PACKETS)
+        .map(|i| match i % 50 {
+            0 => 8192,
+            1..=9 => 512,
+            _ => 24 + i % 40,
+        })
+        .collect()
+}
+
+/// What the backend sends, frames compressed with the threshold of the proxy. The payloads are
+/// random so that large packets do not shrink to nothing
+fn encode_stream(sizes: &[usize], threshold: i32) -> IOResult<Vec<u8>> {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut stream = Vec::new();
+    let mut packet = Vec::new();
+    let mut frame = Vec::new();
+    for (i, size) in sizes.iter().enumerate() {
+        packet.clear();
+        VarInt((i % 0x70) as i32).encode(&mut packet, 5)?;
+        while packet.len() < *size {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            packet.push(seed as u8);
+        }
+        frame.clear();
+        compression::compress(&packet, threshold, &mut frame)?;
+        VarInt(frame.len() as i32).encode(&mut stream, 3)?;
+        stream.extend_from_slice(&frame);
+    }
+    Ok(stream)
+}
+
+/// Forwards the ``packets`` frames of the input, returns the time and the allocations it took
+async fn forward(input: Arc<Vec<u8>>, packets: usize, pooled: bool) -> IOResult<(Duration, usize)> {
+    let threshold = ProxyServer::instance().config().compression_threshold;
+    let backend_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let client_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let backend_address = backend_listener.local_addr()?;
+    let client_address = client_listener.local_addr()?;
+
+    let expected = input.len();
+    let backend = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(backend_address).await?;
+        stream.write_all(&input).await
+    });
+    let client = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(client_address).await?;
+        let mut buf = vec![0; 64 * 1024];
+        let mut received = 0;
+        loop {
+            match stream.read(&mut buf).await? {
+                0 => return Ok::<_, std::io::Error>(received),
+                n => received += n,
+            }
+        }
+    });
+    let (mut read, _) = Stream::Tcp(backend_listener.accept().await?.0).into_split();
+    let (client_read, client_write) = Stream::Tcp(client_listener.accept().await?.0).into_split();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    let (sender, send_queue, write_task) =
+        proxy_handler::spawn_write_task(client_write, threshold, None);
+    let connection = ConnectionHandle::new(
+        "client".to_owned(),
+        sender,
+        client_read,
+        ProtocolState::Game,
+        write_task.abort_handle(),
+        threshold,
+        None,
+        client_address,
+        send_queue,
+    );
+    let mut pool = BufferPool::default();
+    let mut frame_buf = Vec::new();
+    for _ in 0..packets {
+        let frame = if pooled {
+            read_frame(&mut read, pool.get(), &mut None, MAX_FRAME_SIZE).await?;
+            pool.split()
+        } else {
+            read_frame(&mut read, &mut frame_buf, &mut None, MAX_FRAME_SIZE).await?;
+            Bytes::from(std::mem::take(&mut frame_buf))
+        };
+        black_box(compression::peek_packet_id(&frame)?);
+        connection.queue_frame(frame).await?;
+    }
+    // the write task flushes and closes the client connection once the queue is gone
+    drop(connection);
+    write_task.await?;
+    let received = client.await??;
+
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    backend.await??;
+    assert_eq!(received, expected, "the client did not receive every packet");
+    Ok((elapsed, allocations))
+}
+
+fn run(name: &str, warmup: &Arc<Vec<u8>>, input: &Arc<Vec<u8>>, pooled: bool) -> IOResult<()> {
+    ProxyServer::instance().block_on(async {
+        forward(warmup.clone(), PACKETS / 10, pooled).await?;
+        let (elapsed, allocations) = forward(input.clone(), PACKETS, pooled).await?;
+        println!(
+            "{:<8} {:>8.1} ns/packet {:>8.3} allocations/packet {:>8.1} MB/s",
+            name,
+            elapsed.as_nanos() as f64 / PACKETS as f64,
+            allocations as f64 / PACKETS as f64,
+            input.len() as f64 / elapsed.as_secs_f64() / 1_000_000.0
+        );
+        Ok(())
+    })
+}
+
+fn main() -> IOResult<()> {
+    if !server::init(ProxyConfig::default(), None) {
+        return Ok(());
+    }
+    let threshold = ProxyServer::instance().config().compression_threshold;
+    let sizes = packet_sizes();
+    let warmup = Arc::new(encode_stream(&sizes[..PACKETS / 10], threshold)?);
+    let input = Arc::new(encode_stream(&sizes, threshold)?);
+    run("vec", &warmup, &input, false)?;
+    run("pooled", &warmup, &input, true)?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::net::{SocketAddrV4, SocketAddrV6};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
@@ -292,6 +292,13 @@ impl HAProxyMessage {
         Ok(())
     }
 
+    /// Writes the header with a single write, backends may not wait for a header that arrives in pieces
+    pub async fn write_to<W: AsyncWrite + Unpin + ?Sized>(&self, dest: &mut W) -> IOResult<()> {
+        let mut buf = Vec::new();
+        self.encode_async(&mut buf).await?;
+        dest.write_all(&buf).await
+    }
+
     async fn write_padded_string<W: AsyncWrite + Unpin + ?Sized>(
         dest: &mut W,
         s: &str,
@@ -340,6 +347,50 @@ pub struct HAProxyMessageV2 {
     pub addresses: HAProxyAdresses,
 }
 
+impl HAProxyMessageV2 {
+    /// Header of a TCP connection made for a client, addresses are mapped to IPv6 if the families differ
+    pub fn proxy(src: SocketAddr, dst: SocketAddr) -> Self {
+        let src_ip = src.ip().to_canonical();
+        let dst_ip = dst.ip().to_canonical();
+        let addresses = match (src_ip, dst_ip) {
+            (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => HAProxyAdresses::Inet {
+                src: SocketAddrV4::new(src_ip, src.port()),
+                dst: SocketAddrV4::new(dst_ip, dst.port()),
+            },
+            _ => HAProxyAdresses::Inet6 {
+                src: SocketAddrV6::new(Self::to_ipv6(src_ip), src.port(), 0, 0),
+                dst: SocketAddrV6::new(Self::to_ipv6(dst_ip), dst.port(), 0, 0),
+            },
+        };
+        Self {
+            command: HAProxyCommand::Proxy,
+            address_family: match addresses {
+                HAProxyAdresses::Inet { .. } => HAPRoxyAddressFamily::Inet,
+                _ => HAPRoxyAddressFamily::Inet6,
+            },
+            transport_protocol: HAProxyTransportProtocol::Stream,
+            addresses,
+        }
+    }
+
+    /// Header of a connection the proxy makes on its own like a health check, the backend uses the real address
+    pub fn local() -> Self {
+        Self {
+            command: HAProxyCommand::Local,
+            address_family: HAPRoxyAddressFamily::Unspec,
+            transport_protocol: HAProxyTransportProtocol::Unspec,
+            addresses: HAProxyAdresses::Unspec,
+        }
+    }
+
+    fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+        match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HAProxyCommand {
     Local,
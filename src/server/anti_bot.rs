@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    rate_limit::{RateLimitConfig, RateLimiter},
+    throttle, ProxyServer,
+};
+
+/// Addresses and names remembered at most, more addresses are not tracked until old ones expire
+/// and a new name replaces the one that did not log in for the longest time
+const MAX_ENTRIES: usize = 65536;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AntiBotConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Login attempts per second across the proxy that start the protected mode
+    #[serde(default = "default_attack_threshold")]
+    pub attack_threshold: u32,
+    /// The protected mode ends once the login attempts per second stay below this for ``exit_delay`` milliseconds
+    #[serde(default = "default_exit_threshold")]
+    pub exit_threshold: u32,
+    #[serde(default = "default_exit_delay")]
+    pub exit_delay: u64,
+    /// Only addresses that pinged the proxy in the server list within ``ping_validity`` milliseconds may join
+    #[serde(default = "default_enabled")]
+    pub require_ping: bool,
+    #[serde(default = "default_ping_validity")]
+    pub ping_validity: u64,
+    /// Kicks the first join of an address and lets it in if it reconnects within ``reconnect_timeout`` milliseconds
+    #[serde(default = "default_enabled")]
+    pub reconnect_check: bool,
+    #[serde(default = "default_reconnect_timeout")]
+    pub reconnect_timeout: u64,
+    /// Milliseconds an address that passed the reconnect check is trusted
+    #[serde(default = "default_verified_duration")]
+    pub verified_duration: u64,
+    /// Joins of names that never logged in before, shared by everyone
+    #[serde(default = "default_new_names")]
+    pub new_names: RateLimitConfig,
+    /// Milliseconds a name is known after its last login
+    #[serde(default = "default_known_name_duration")]
+    pub known_name_duration: u64,
+    /// Names matching one of these are rejected while protected, ``*`` matches anything and ``?`` one character
+    #[serde(default)]
+    pub blocked_names: Vec<String>,
+    #[serde(default = "default_ping_message")]
+    pub ping_message: String,
+    #[serde(default = "default_reconnect_message")]
+    pub reconnect_message: String,
+    #[serde(default = "default_new_name_message")]
+    pub new_name_message: String,
+    #[serde(default = "default_blocked_name_message")]
+    pub blocked_name_message: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_attack_threshold() -> u32 {
+    30
+}
+
+fn default_exit_threshold() -> u32 {
+    5
+}
+
+fn default_exit_delay() -> u64 {
+    60000
+}
+
+fn default_ping_validity() -> u64 {
+    300000
+}
+
+fn default_reconnect_timeout() -> u64 {
+    30000
+}
+
+fn default_verified_duration() -> u64 {
+    3600000
+}
+
+fn default_new_names() -> RateLimitConfig {
+    RateLimitConfig::new(5, 1.0)
+}
+
+fn default_known_name_duration() -> u64 {
+    30 * 24 * 3600000
+}
+
+fn default_ping_message() -> String {
+    "&cPlease add the server to your server list and refresh it before joining".to_owned()
+}
+
+fn default_reconnect_message() -> String {
+    "&eThe server is under attack, please reconnect".to_owned()
+}
+
+fn default_new_name_message() -> String {
+    "&cToo many new players are joining, please try again in a moment".to_owned()
+}
+
+fn default_blocked_name_message() -> String {
+    "&cThis name is not allowed".to_owned()
+}
+
+impl Default for AntiBotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            attack_threshold: default_attack_threshold(),
+            exit_threshold: default_exit_threshold(),
+            exit_delay: default_exit_delay(),
+            require_ping: default_enabled(),
+            ping_validity: default_ping_validity(),
+            reconnect_check: default_enabled(),
+            reconnect_timeout: default_reconnect_timeout(),
+            verified_duration: default_verified_duration(),
+            new_names: default_new_names(),
+            known_name_duration: default_known_name_duration(),
+            blocked_names: Vec::new(),
+            ping_message: default_ping_message(),
+            reconnect_message: default_reconnect_message(),
+            new_name_message: default_new_name_message(),
+            blocked_name_message: default_blocked_name_message(),
+        }
+    }
+}
+
+static PROTECTED: AtomicBool = AtomicBool::new(false);
+static JOINS: AtomicU32 = AtomicU32::new(0);
+
+lazy_static! {
+    static ref PINGS: Mutex<HashMap<IpAddr, Instant>> = Mutex::new(HashMap::new());
+    /// address and name of the first join and when it happened
+    static ref PENDING: Mutex<HashMap<IpAddr, (String, Instant)>> = Mutex::new(HashMap::new());
+    static ref VERIFIED: Mutex<HashMap<IpAddr, Instant>> = Mutex::new(HashMap::new());
+    /// lowercase names and their last login
+    static ref KNOWN_NAMES: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+    static ref NEW_NAMES: RateLimiter<()> = {
+        let config = &ProxyServer::instance().config().anti_bot.new_names;
+        RateLimiter::new(config.capacity, config.refill_rate)
+    };
+}
+
+pub fn is_protected() -> bool {
+    PROTECTED.load(Ordering::Relaxed)
+}
+
+/// Counts a login attempt for the attack detection
+pub fn record_join() {
+    JOINS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_ping(ip: IpAddr) {
+    if !ProxyServer::instance().config().anti_bot.enabled {
+        return;
+    }
+    let mut pings = PINGS.lock().unwrap();
+    if pings.len() < MAX_ENTRIES || pings.contains_key(&ip) {
+        pings.insert(ip, Instant::now());
+    }
+}
+
+/// Names of players that logged in successfully are not throttled as new names
+pub fn remember_name(name: &str) {
+    let name = name.to_ascii_lowercase();
+    let mut names = KNOWN_NAMES.lock().unwrap();
+    if names.len() >= MAX_ENTRIES && !names.contains_key(&name) {
+        // the name that did not log in for the longest time makes room
+        if let Some(oldest) = names
+            .iter()
+            .min_by_key(|(_, time)| **time)
+            .map(|(name, _)| name.clone())
+        {
+            names.remove(&oldest);
+        }
+    }
+    names.insert(name, Instant::now());
+}
+
+pub fn start() {
+    let config = &ProxyServer::instance().config().anti_bot;
+    if !config.enabled {
+        return;
+    }
+    let exit_delay = Duration::from_millis(config.exit_delay);
+    ProxyServer::instance().spawn_task(async move {
+        let mut calm_since = None;
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let config = &ProxyServer::instance().config().anti_bot;
+            let joins = JOINS.swap(0, Ordering::Relaxed);
+            if !is_protected() {
+                if joins >= config.attack_threshold {
+                    warn!(
+                        "Bot attack detected with {} joins per second, protection enabled",
+                        joins
+                    );
+                    PROTECTED.store(true, Ordering::Relaxed);
+                    calm_since = None;
+                }
+            } else if joins < config.exit_threshold {
+                let since = *calm_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= exit_delay {
+                    info!("Bot attack is over, protection disabled");
+                    PROTECTED.store(false, Ordering::Relaxed);
+                    PENDING.lock().unwrap().clear();
+                }
+            } else {
+                calm_since = None;
+            }
+            expire();
+        }
+    });
+}
+
+fn expire() {
+    let config = &ProxyServer::instance().config().anti_bot;
+    let ping_validity = Duration::from_millis(config.ping_validity);
+    let reconnect_timeout = Duration::from_millis(config.reconnect_timeout);
+    let verified_duration = Duration::from_millis(config.verified_duration);
+    let known_name_duration = Duration::from_millis(config.known_name_duration);
+    PINGS
+        .lock()
+        .unwrap()
+        .retain(|_, time| time.elapsed() < ping_validity);
+    PENDING
+        .lock()
+        .unwrap()
+        .retain(|_, (_, time)| time.elapsed() < reconnect_timeout);
+    VERIFIED
+        .lock()
+        .unwrap()
+        .retain(|_, time| time.elapsed() < verified_duration);
+    KNOWN_NAMES
+        .lock()
+        .unwrap()
+        .retain(|_, time| time.elapsed() < known_name_duration);
+}
+
+/// Matches a name against a pattern where ``*`` matches anything and ``?`` a single character
+fn matches_pattern(name: &[u8], pattern: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| matches_pattern(&name[skip..], rest)),
+        Some((first, rest)) => match name.split_first() {
+            Some((c, name)) if *first == b'?' || c.eq_ignore_ascii_case(first) => {
+                matches_pattern(name, rest)
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Checks a login while the protected mode is active, the error is the kick message
+pub fn check_login(ip: IpAddr, name: &str) -> Result<(), String> {
+    let config = &ProxyServer::instance().config().anti_bot;
+    if !config.enabled || !is_protected() || throttle::is_exempt(ip) {
+        return Ok(());
+    }
+    let kick = |message: &str| Err(message.replace('&', "§"));
+    if config
+        .blocked_names
+        .iter()
+        .any(|pattern| matches_pattern(name.as_bytes(), pattern.as_bytes()))
+    {
+        return kick(&config.blocked_name_message);
+    }
+    if config.require_ping && !PINGS.lock().unwrap().contains_key(&ip) {
+        return kick(&config.ping_message);
+    }
+    if config.reconnect_check && !VERIFIED.lock().unwrap().contains_key(&ip) {
+        let mut pending = PENDING.lock().unwrap();
+        match pending.get(&ip) {
+            Some((pending_name, _)) if pending_name == name => {
+                pending.remove(&ip);
+                let mut verified = VERIFIED.lock().unwrap();
+                if verified.len() < MAX_ENTRIES {
+                    verified.insert(ip, Instant::now());
+                }
+            }
+            _ => {
+                if pending.len() < MAX_ENTRIES {
+                    pending.insert(ip, (name.to_owned(), Instant::now()));
+                }
+                return kick(&config.reconnect_message);
+            }
+        }
+    }
+    if !KNOWN_NAMES
+        .lock()
+        .unwrap()
+        .contains_key(&name.to_ascii_lowercase())
+        && config.new_names.enabled
+        && !NEW_NAMES.check(())
+    {
+        return kick(&config.new_name_message);
+    }
+    Ok(())
+}
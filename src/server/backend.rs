@@ -4,16 +4,19 @@ use std::{
     net::{IpAddr, SocketAddr},
     ops::DerefMut,
     pin::Pin,
+    sync::Arc,
+    time::Duration,
 };
+use bytes::BufMut;
 use log::warn;
 use rand::RngCore;
 use rsa::{Pkcs1v15Encrypt, RsaPublicKey};
-use tokio::net::{TcpStream, ToSocketAddrs};
 use uuid::Uuid;
 
 use crate::{
     auth::LoginResult,
     chat::Text,
+    haproxy::{HAProxyMessage, HAProxyMessageV2},
     server::{
         encryption::{PacketDecryption, PacketEncryption},
         packets::{EncryptionResponse, Kick, Packet},
@@ -25,15 +28,19 @@ use crate::{
 use self::packets::{LoginAcknowledged, LoginDisconnect, SetCompression};
 
 use super::{
+    buffer_pool::BufferPool,
+    circuit_breaker, compression, limbo,
+    limits::MAX_FRAME_SIZE,
+    network::{self, BatchWriter, SendQueue, Stream},
     packet_handler::ServerPacketHandler,
     packet_ids::{PacketRegistry, ServerPacketType},
     packets::{
-        self, encode_and_send_packet, read_and_decode_packet, CookieRequest, CookieResponse,
-        EncryptionRequest, Handshake, LoginPluginRequest, LoginPluginResponse, LoginRequest,
-        LoginSuccess, PlayerPublicKey, ProtocolState, PROTOCOL_STATE_LOGIN,
+        self, encode_and_send_packet, read_and_decode_packet, read_frame, CookieRequest,
+        CookieResponse, EncryptionRequest, Handshake, LoginPluginRequest, LoginPluginResponse,
+        LoginRequest, LoginSuccess, PlayerPublicKey, ProtocolState, PROTOCOL_STATE_LOGIN,
     },
     proxy_handler::{ClientHandle, ConnectionHandle, PacketSending},
-    ProxiedPlayer, ProxyServer,
+    ProxiedPlayer, ProxyServer, ServerInfo,
 };
 
 #[derive(Debug)]
@@ -43,6 +50,10 @@ pub enum ConnectError {
     Kicked(String),
     ServerInOnlineMode,
     InvalidPublicKeyFormat,
+    ConnectTimeout,
+    LoginTimeout,
+    /// the server failed too often, contains the time until the next attempt
+    CircuitOpen(Duration),
 }
 
 impl std::fmt::Display for ConnectError {
@@ -53,15 +64,47 @@ impl std::fmt::Display for ConnectError {
             Self::Kicked(reason) => write!(f, "Kicked: {}", reason),
             Self::ServerInOnlineMode => write!(f, "Server is in online mode"),
             Self::InvalidPublicKeyFormat => write!(f, "Invalid public key format"),
+            Self::ConnectTimeout => write!(f, "Timed out connecting to server"),
+            Self::LoginTimeout => write!(f, "Timed out in login state"),
+            Self::CircuitOpen(retry) => {
+                write!(f, "Circuit breaker is open for another {}ms", retry.as_millis())
+            }
         }
     }
 }
 
+impl ConnectError {
+    /// The reason shown to the player, without the details meant for the log
+    pub fn user_message(&self) -> String {
+        match self {
+            Self::SocketConnectError(_) | Self::ConnectTimeout => "The server is offline".to_owned(),
+            Self::IO(_) => "Lost connection to the server while logging in".to_owned(),
+            Self::Kicked(reason) => reason.clone(),
+            Self::ServerInOnlineMode | Self::InvalidPublicKeyFormat => {
+                "The server is misconfigured".to_owned()
+            }
+            Self::LoginTimeout => "The server did not respond in time".to_owned(),
+            Self::CircuitOpen(retry) => format!(
+                "The server is unavailable, try again in {} seconds",
+                retry.as_secs().max(1)
+            ),
+        }
+    }
+
+    /// Whether the error means the server is unreachable or broken rather than refusing the player
+    fn is_server_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::SocketConnectError(_) | Self::IO(_) | Self::ConnectTimeout | Self::LoginTimeout
+        )
+    }
+}
+
 impl std::error::Error for ConnectError {}
 
 pub struct EstablishedBackend {
     profile: LoginResult,
-    stream: TcpStream,
+    stream: Stream,
     compression_threshold: i32,
     version: i32,
     encryption: Option<(PacketEncryption, PacketDecryption)>,
@@ -84,12 +127,12 @@ impl EstablishedBackend {
             ..
         } = self;
         let synced_protocol_state = partner.connection.protocol_state.clone();
-        let (read, mut write) = stream.into_split();
+        let (read, write) = stream.into_split();
         let player = partner.player.clone();
         let version = partner.version;
 
         let partner_handle = partner.connection.clone();
-        let (mut encryption, decryption) = match encryption {
+        let (encryption, decryption) = match encryption {
             Some(encryption) => (Some(encryption.0), Some(encryption.1)),
             None => (None, None),
         };
@@ -98,17 +141,15 @@ impl EstablishedBackend {
         let player_ = player.clone();
         let read_task = tokio::spawn(async move {
             let self_handle = handle_receiver.await.unwrap();
-            let mut protocol_buf = Vec::new();
+            let mut pool = BufferPool::default();
             let mut read = self_handle.reader.lock().await;
             let mut decryption = self_handle.decryption.lock().await;
-            let mut read_buf = Vec::new();
             loop {
-                let res = read_and_decode_packet(
+                let res = read_frame(
                     read.deref_mut(),
-                    &mut read_buf,
-                    &mut protocol_buf,
-                    compression_threshold,
+                    pool.get(),
                     &mut decryption,
+                    MAX_FRAME_SIZE,
                 )
                 .await;
 
@@ -116,17 +157,46 @@ impl EstablishedBackend {
                     self_handle.disconnect(&e.to_string()).await;
                     break;
                 }
-
-                let packet_id = VarInt::decode_simple(&mut Cursor::new(&read_buf));
+                let frame = pool.split();
+
+                let packet_id = if compression_threshold != -1 {
+                    compression::peek_packet_id(&frame)
+                } else {
+                    VarInt::decode_simple(&mut Cursor::new(&frame))
+                        .map(|packet_id| packet_id.get())
+                };
                 if let Err(e) = packet_id {
                     self_handle.disconnect(&e.to_string()).await;
                     break;
                 }
-                let packet_id = packet_id.unwrap().get();
+                let packet_id = packet_id.unwrap();
+
+                // packets the handler does not inspect are forwarded without decompressing them
+                if compression_threshold == partner.connection.compression_threshold
+                    && PacketRegistry::instance()
+                        .get_server_packet_type(self_handle.protocol_state(), version, packet_id)
+                        .is_none()
+                {
+                    if let Err(e) = partner.connection.queue_frame(frame).await {
+                        self_handle.disconnect(&e.to_string()).await;
+                        break;
+                    }
+                    continue;
+                }
+
+                let packet = if compression_threshold != -1 {
+                    if let Err(e) = compression::decompress(&frame, &mut pool.get().writer()) {
+                        self_handle.disconnect(&e.to_string()).await;
+                        break;
+                    }
+                    pool.split()
+                } else {
+                    frame
+                };
 
                 let res = ServerPacketHandler::handle_packet(
                     packet_id,
-                    &read_buf[VarInt::get_size(packet_id)..],
+                    &packet[VarInt::get_size(packet_id)..],
                     version,
                     &player_,
                     &self_handle,
@@ -138,43 +208,52 @@ impl EstablishedBackend {
                     break;
                 }
                 if res.unwrap() {
-                    if let Err(e) = partner.connection.queue_packet(read_buf.clone(), false).await {
+                    if let Err(e) = partner.connection.queue_packet(packet, false).await {
                         // TODO: handle when client is disconnected
                         self_handle.disconnect(&e.to_string()).await;
                         break;
                     }
                 }
-                read_buf.clear();
             }
         });
 
         let (sender, mut receiver) = tokio::sync::mpsc::channel(1000);
+        let send_queue = Arc::new(SendQueue::default());
+        let taken = send_queue.clone();
 
         let write_task = tokio::spawn(async move {
-            let mut protocol_buf = Vec::new();
-            while let Some(event) = receiver.recv().await {
-                match event {
-                    PacketSending::Packet(packet, bypass) => {
-                        if encode_and_send_packet(
-                            &mut write,
-                            &packet,
-                            &mut protocol_buf,
-                            compression_threshold,
-                            &mut encryption,
-                        )
-                        .await
-                        .is_err()
-                        {
-                            // could not forward packet to player, he disconnected
-                            break;
+            let mut writer = BatchWriter::new(write, compression_threshold, encryption);
+            'batches: while let Some(event) = receiver.recv().await {
+                let deadline = writer.deadline();
+                let mut next = Some(event);
+                while let Some(event) = next {
+                    let res = match event {
+                        PacketSending::Packet(packet, _bypass) => {
+                            taken.release(packet.len());
+                            writer.push_packet(&packet)
                         }
+                        PacketSending::Frame(frame) => {
+                            taken.release(frame.len());
+                            writer.push_frame(&frame)
+                        }
+                        PacketSending::Sync(sender) => {
+                            let res = writer.flush().await;
+                            let _ = sender.send(());
+                            res
+                        }
+                        _ => Ok(()),
+                    };
+                    if res.is_err() {
+                        break 'batches;
                     }
-                    PacketSending::Sync(sender) => {
-                        let _ = sender.send(());
-                    }
-                    _ => {}
+                    next = writer.next_event(&mut receiver, deadline).await;
+                }
+                if writer.flush().await.is_err() {
+                    // could not forward packet to player, he disconnected
+                    break;
                 }
             }
+            taken.wake();
         });
 
         let mut handle = ConnectionHandle::new(
@@ -186,6 +265,7 @@ impl EstablishedBackend {
             compression_threshold,
             decryption,
             address,
+            send_queue,
         );
 
         log::info!("[{}] <-> [{}]: connected", player_name, server_name);
@@ -193,6 +273,7 @@ impl EstablishedBackend {
         let disconnect_lock = handle.disconnect_wait.clone();
 
         handle.protocol_state = synced_protocol_state; // synchronize protocol state
+        handle.server_name = Some(server_name.to_owned());
         handle
             .read_task
             .lock()
@@ -208,14 +289,18 @@ impl EstablishedBackend {
             let disconnect_guard = disconnect_lock.write().await;
             let _ = write_task.await;
             drop(disconnect_guard);
-            let servers = ProxyServer::instance().servers().read().await;
-            for server_name in servers.get_priorities() {
-                let server = servers.get_server_by_name(&server_name);
-                if let Some(server) = server {
-                    if switch_server_helper(player.clone(), server_name.to_string()).await {
-                        return;
-                    }
+            let priorities = ProxyServer::instance().servers().read().await.get_priorities().to_vec();
+            for server_name in priorities {
+                if switch_server_helper(player.clone(), server_name).await {
+                    return;
+                }
+            }
+
+            if limbo::is_enabled() {
+                if let Some(player) = player.upgrade() {
+                    limbo::enter(player);
                 }
+                return;
             }
 
             let mut buf = vec![];
@@ -281,19 +366,67 @@ fn sanitize_address(addr: SocketAddr) -> std::io::Result<String> {
     Ok(address_str)
 }
 
-pub async fn connect<A: ToSocketAddrs>(
+#[allow(clippy::too_many_arguments)]
+pub async fn connect(
+    client_ip: SocketAddr,
+    proxy_address: SocketAddr,
+    server: &ServerInfo,
+    hs_host: String,
+    hs_port: u16,
+    profile: LoginResult,
+    player_public_key: Option<PlayerPublicKey>,
+    version: i32,
+) -> Result<EstablishedBackend, ConnectError> {
+    circuit_breaker::check(&server.label).map_err(ConnectError::CircuitOpen)?;
+    let result = tokio::time::timeout(
+        Duration::from_millis(server.login_timeout),
+        login(
+            client_ip,
+            proxy_address,
+            server,
+            hs_host,
+            hs_port,
+            profile,
+            player_public_key,
+            version,
+        ),
+    )
+    .await
+    .unwrap_or(Err(ConnectError::LoginTimeout));
+    match result {
+        Err(ref e) if e.is_server_failure() => circuit_breaker::record_failure(&server.label),
+        // a kick or a rejected login is still an answer of the server, it closes a half-open breaker
+        _ => circuit_breaker::record_success(&server.label),
+    }
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn login(
     client_ip: SocketAddr,
-    addr: A,
+    proxy_address: SocketAddr,
+    server: &ServerInfo,
     hs_host: String,
     hs_port: u16,
     mut profile: LoginResult,
     player_public_key: Option<PlayerPublicKey>,
     version: i32,
 ) -> Result<EstablishedBackend, ConnectError> {
-    let mut stream = TcpStream::connect(addr)
-        .await
-        .map_err(ConnectError::SocketConnectError)?;
+    let mut stream = tokio::time::timeout(
+        Duration::from_millis(server.connect_timeout),
+        network::connect(&server.address),
+    )
+    .await
+    .map_err(|_| ConnectError::ConnectTimeout)?
+    .map_err(ConnectError::SocketConnectError)?;
     let address = stream.peer_addr().map_err(ConnectError::IO)?;
+    if server.proxy_protocol {
+        // the backend sees the connection as the client made it, to the proxy
+        HAProxyMessage::from(HAProxyMessageV2::proxy(client_ip, proxy_address))
+            .write_to(&mut stream)
+            .await
+            .map_err(ConnectError::IO)?;
+    }
 
     let mut write_buf = Vec::new();
     let mut protocol_buf = Vec::new();
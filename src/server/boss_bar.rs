@@ -0,0 +1,91 @@
+use uuid::Uuid;
+
+use crate::chat::Text;
+
+use super::packets::{BossBarAction, BossBarPacket};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BossBarColor {
+    Pink,
+    Blue,
+    Red,
+    Green,
+    Yellow,
+    Purple,
+    White,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BossBarDivision {
+    Progress,
+    Notches6,
+    Notches10,
+    Notches12,
+    Notches20,
+}
+
+/// A boss bar owned by the proxy, it stays visible when the player switches servers
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossBar {
+    pub uuid: Uuid,
+    pub title: Text,
+    /// between 0 and 1
+    pub health: f32,
+    pub color: BossBarColor,
+    pub division: BossBarDivision,
+    /// 0x01 darkens the sky, 0x02 plays boss music, 0x04 creates fog
+    pub flags: u8,
+}
+
+impl BossBar {
+    pub fn new<T: Into<Text>>(title: T, color: BossBarColor) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            title: title.into(),
+            health: 1.0,
+            color,
+            division: BossBarDivision::Progress,
+            flags: 0,
+        }
+    }
+
+    pub fn add_packet(&self) -> BossBarPacket {
+        BossBarPacket {
+            uuid: self.uuid,
+            action: BossBarAction::Add {
+                title: self.title.clone(),
+                health: self.health,
+                color: self.color as i32,
+                division: self.division as i32,
+                flags: self.flags,
+            },
+        }
+    }
+
+    /// The packets needed to turn `old` into this boss bar on the client
+    pub fn update_packets(&self, old: &BossBar) -> Vec<BossBarPacket> {
+        let mut actions = Vec::new();
+        if self.title != old.title {
+            actions.push(BossBarAction::UpdateTitle(self.title.clone()));
+        }
+        if self.health != old.health {
+            actions.push(BossBarAction::UpdateHealth(self.health));
+        }
+        if self.color != old.color || self.division != old.division {
+            actions.push(BossBarAction::UpdateStyle {
+                color: self.color as i32,
+                division: self.division as i32,
+            });
+        }
+        if self.flags != old.flags {
+            actions.push(BossBarAction::UpdateFlags(self.flags));
+        }
+        actions
+            .into_iter()
+            .map(|action| BossBarPacket {
+                uuid: self.uuid,
+                action,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,41 @@
+use bytes::{Bytes, BytesMut};
+
+/// Size of the allocations packets are cut from
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hands out packet buffers cut from one large allocation. Packets are reference counted slices
+/// of it, the allocation is reused once every packet cut from it was dropped.
+pub struct BufferPool {
+    chunk: BytesMut,
+    chunk_size: usize,
+}
+
+impl BufferPool {
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk: BytesMut::with_capacity(chunk_size),
+            chunk_size,
+        }
+    }
+
+    /// An empty buffer to write the next packet into, it grows like a ``Vec`` if the packet does not fit
+    pub fn get(&mut self) -> &mut BytesMut {
+        debug_assert!(self.chunk.is_empty());
+        if self.chunk.capacity() < self.chunk_size / 4 {
+            // reclaims the allocation if no packet of it is alive anymore
+            self.chunk.reserve(self.chunk_size);
+        }
+        &mut self.chunk
+    }
+
+    /// Takes what was written into the buffer returned by ``get``
+    pub fn split(&mut self) -> Bytes {
+        self.chunk.split().freeze()
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(CHUNK_SIZE)
+    }
+}
@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    chat::{Text, TextContent},
+    util::{Handle, IOResult, WeakHandle},
+};
+
+use super::{
+    packets::{self, MessageAcknowledgement},
+    ProxiedPlayer, ProxyServer,
+};
+
+pub type ChatHandler = fn(event: &mut ChatEvent);
+pub type ServerChatHandler = fn(event: &mut ServerChatEvent);
+
+pub const DEFAULT_CHAT_FORMAT: &str = "<{player}> {message}";
+pub const STAFF_CHAT_PERMISSION: &str = "crust.chat.staff";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatConfig {
+    /// Sends chat messages to every player on the proxy instead of only the current server
+    #[serde(default)]
+    pub global: bool,
+    #[serde(default = "default_global_format")]
+    pub global_format: String,
+    /// Messages starting with it go to every player with the ``crust.chat.staff`` permission
+    #[serde(default)]
+    pub staff_prefix: Option<String>,
+    #[serde(default = "default_staff_format")]
+    pub staff_format: String,
+    /// Replaced with ``*`` in player messages, also in messages from backends like ``/msg``
+    #[serde(default)]
+    pub filtered_words: Vec<String>,
+}
+
+fn default_global_format() -> String {
+    "&7[{server}] &r<{player}> {message}".to_owned()
+}
+
+fn default_staff_format() -> String {
+    "&c[Staff] &r{player}: {message}".to_owned()
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            global: false,
+            global_format: default_global_format(),
+            staff_prefix: None,
+            staff_format: default_staff_format(),
+            filtered_words: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatDestination {
+    /// Forwarded to the server the player is connected to
+    Server,
+    /// Every player on the proxy
+    Global,
+    /// Every player with the given permission
+    Permission(String),
+    Players(Vec<Uuid>),
+}
+
+/// A chat message sent by a player
+pub struct ChatEvent {
+    pub player: WeakHandle<ProxiedPlayer>,
+    pub message: String,
+    pub cancelled: bool,
+    pub destination: ChatDestination,
+    /// Used when the proxy has to deliver the message itself, ``{player}`` and ``{message}`` are replaced
+    pub format: String,
+}
+
+/// A chat message sent by a backend to a player
+pub struct ServerChatEvent {
+    pub receiver: WeakHandle<ProxiedPlayer>,
+    /// None for system messages
+    pub sender: Option<Uuid>,
+    pub sender_name: Option<Text>,
+    pub message: Text,
+    pub cancelled: bool,
+}
+
+pub struct ChatPipelineBuilder {
+    chat_handlers: Vec<ChatHandler>,
+    server_chat_handlers: Vec<ServerChatHandler>,
+}
+
+impl ChatPipelineBuilder {
+    pub fn new() -> Self {
+        Self {
+            chat_handlers: Vec::new(),
+            server_chat_handlers: Vec::new(),
+        }
+    }
+
+    pub fn add_chat_handler(&mut self, handler: ChatHandler) {
+        self.chat_handlers.push(handler);
+    }
+
+    pub fn chat_handler(mut self, handler: ChatHandler) -> Self {
+        self.add_chat_handler(handler);
+        self
+    }
+
+    pub fn add_server_chat_handler(&mut self, handler: ServerChatHandler) {
+        self.server_chat_handlers.push(handler);
+    }
+
+    pub fn server_chat_handler(mut self, handler: ServerChatHandler) -> Self {
+        self.add_server_chat_handler(handler);
+        self
+    }
+
+    pub fn build(self) -> ChatPipeline {
+        ChatPipeline {
+            chat_handlers: self.chat_handlers,
+            server_chat_handlers: self.server_chat_handlers,
+        }
+    }
+}
+
+/// Handlers are called in registration order and see the changes of the handlers before them
+pub struct ChatPipeline {
+    chat_handlers: Vec<ChatHandler>,
+    server_chat_handlers: Vec<ServerChatHandler>,
+}
+
+impl ChatPipeline {
+    pub fn has_chat_handlers(&self) -> bool {
+        !self.chat_handlers.is_empty()
+    }
+
+    pub fn has_server_chat_handlers(&self) -> bool {
+        !self.server_chat_handlers.is_empty()
+    }
+
+    pub fn call_chat(&self, event: &mut ChatEvent) {
+        for handler in &self.chat_handlers {
+            handler(event);
+        }
+    }
+
+    pub fn call_server_chat(&self, event: &mut ServerChatEvent) {
+        for handler in &self.server_chat_handlers {
+            handler(event);
+        }
+    }
+}
+
+/// Registers the built-in handlers enabled in the chat config
+pub fn register_all(builder: ChatPipelineBuilder, config: &ChatConfig) -> ChatPipelineBuilder {
+    let mut builder = builder;
+    if !config.filtered_words.is_empty() {
+        builder = builder
+            .chat_handler(filter_chat)
+            .server_chat_handler(filter_server_chat);
+    }
+    if config.staff_prefix.is_some() {
+        builder = builder.chat_handler(staff_chat);
+    }
+    if config.global {
+        builder = builder.chat_handler(global_chat);
+    }
+    builder
+}
+
+/// Replaces every character of the filtered words with ``*``, None if there was none
+fn filter(message: &str) -> Option<String> {
+    let chars: Vec<char> = message.chars().collect();
+    let mut censored = vec![false; chars.len()];
+    for word in &ProxyServer::instance().config().chat.filtered_words {
+        let word: Vec<char> = word.chars().collect();
+        if word.is_empty() || word.len() > chars.len() {
+            continue;
+        }
+        for start in 0..=chars.len() - word.len() {
+            let found = chars[start..start + word.len()]
+                .iter()
+                .zip(&word)
+                .all(|(char, other)| char.to_lowercase().eq(other.to_lowercase()));
+            if found {
+                censored[start..start + word.len()].fill(true);
+            }
+        }
+    }
+    if !censored.contains(&true) {
+        return None;
+    }
+    Some(
+        chars
+            .into_iter()
+            .zip(censored)
+            .map(|(char, censored)| if censored { '*' } else { char })
+            .collect(),
+    )
+}
+
+/// Filters the literal parts of the text and keeps the style, words split over several parts are not found
+fn filter_text(text: &mut Text) {
+    match text.content {
+        TextContent::Literal(ref mut literal) => {
+            if let Some(filtered) = filter(literal) {
+                *literal = filtered;
+            }
+        }
+        TextContent::Translation {
+            with: Some(ref mut with),
+            ..
+        } => with.iter_mut().for_each(filter_text),
+        _ => {}
+    }
+    text.extra.iter_mut().for_each(filter_text);
+}
+
+fn filter_chat(event: &mut ChatEvent) {
+    if let Some(message) = filter(&event.message) {
+        event.message = message;
+    }
+}
+
+fn filter_server_chat(event: &mut ServerChatEvent) {
+    // only messages of players, system messages are left to the backend
+    if event.sender_name.is_none() {
+        return;
+    }
+    filter_text(&mut event.message);
+}
+
+fn staff_chat(event: &mut ChatEvent) {
+    let config = &ProxyServer::instance().config().chat;
+    let prefix = match config.staff_prefix {
+        Some(ref prefix) if !prefix.is_empty() => prefix,
+        _ => return,
+    };
+    let message = match event.message.strip_prefix(prefix.as_str()) {
+        Some(message) => message.trim_start().to_owned(),
+        None => return,
+    };
+    match event.player.upgrade() {
+        Some(player) if player.has_permission(STAFF_CHAT_PERMISSION) => {}
+        _ => return,
+    }
+    event.message = message;
+    event.destination = ChatDestination::Permission(STAFF_CHAT_PERMISSION.to_owned());
+    event.format = config.staff_format.clone();
+}
+
+fn global_chat(event: &mut ChatEvent) {
+    if event.cancelled || event.destination != ChatDestination::Server {
+        return;
+    }
+    let server = event
+        .player
+        .upgrade()
+        .and_then(|player| player.current_server.clone())
+        .unwrap_or_default();
+    event.destination = ChatDestination::Global;
+    event.format = ProxyServer::instance()
+        .config()
+        .chat
+        .global_format
+        .replace("{server}", &server);
+}
+
+pub async fn call_chat(mut event: ChatEvent) -> IOResult<ChatEvent> {
+    // Needs to be blocking because handlers are executed synchronously
+    Ok(tokio::task::spawn_blocking(move || {
+        ProxyServer::instance().chat_pipeline().call_chat(&mut event);
+        event
+    })
+    .await?)
+}
+
+pub async fn call_server_chat(mut event: ServerChatEvent) -> IOResult<ServerChatEvent> {
+    Ok(tokio::task::spawn_blocking(move || {
+        ProxyServer::instance()
+            .chat_pipeline()
+            .call_server_chat(&mut event);
+        event
+    })
+    .await?)
+}
+
+/// Tells the backend about messages the client acknowledged with a packet the proxy did not forward,
+/// otherwise the backend rejects the next signed message of the client
+pub async fn acknowledge_messages(player: &ProxiedPlayer, offset: i32) -> IOResult<()> {
+    if offset <= 0 {
+        return Ok(());
+    }
+    if let Some(ref server_handle) = player.server_handle {
+        if let Some(data) = packets::get_full_client_packet_buf(
+            &MessageAcknowledgement { offset },
+            player.protocol_version,
+            server_handle.protocol_state(),
+        )? {
+            server_handle.queue_packet(data, false).await?;
+        }
+    }
+    Ok(())
+}
+
+pub fn format_message(format: &str, player: &str, message: &str) -> Text {
+    Text::new(
+        format
+            .replace('&', "§")
+            .replace("{player}", player)
+            .replace("{message}", message),
+    )
+}
+
+/// Delivers a message the proxy took over from the backend as system message
+pub async fn deliver(sender: &Handle<ProxiedPlayer>, event: &ChatEvent) {
+    let text = format_message(&event.format, &sender.name, &event.message);
+    let players = ProxyServer::instance()
+        .player_by_name
+        .read()
+        .await
+        .values()
+        .filter_map(WeakHandle::upgrade)
+        .collect::<Vec<_>>();
+    for player in players {
+        let receives = match event.destination {
+            ChatDestination::Server => player.current_server == sender.current_server,
+            ChatDestination::Global => true,
+            ChatDestination::Permission(ref permission) => player.has_permission(permission),
+            ChatDestination::Players(ref uuids) => uuids.contains(&player.uuid),
+        };
+        if receives {
+            player.send_message(text.clone()).await.ok();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::ProxyServer;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Failed connections in a row until no more connections to the server are attempted
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Milliseconds until a single connection is attempted again
+    #[serde(default = "default_open_duration")]
+    pub open_duration: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_open_duration() -> u64 {
+    30000
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            failure_threshold: default_failure_threshold(),
+            open_duration: default_open_duration(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// one connection is let through to find out if the server is back
+    HalfOpen { since: Instant },
+}
+
+lazy_static! {
+    static ref BREAKERS: Mutex<HashMap<String, State>> = Mutex::new(HashMap::new());
+}
+
+fn open_duration() -> Duration {
+    Duration::from_millis(ProxyServer::instance().config().circuit_breaker.open_duration)
+}
+
+/// Whether a connection to the server may be attempted, otherwise the time until the next attempt
+pub fn check(server: &str) -> Result<(), Duration> {
+    if !ProxyServer::instance().config().circuit_breaker.enabled {
+        return Ok(());
+    }
+    let mut breakers = BREAKERS.lock().unwrap();
+    let state = match breakers.get_mut(server) {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let now = Instant::now();
+    match *state {
+        State::Closed { .. } => Ok(()),
+        State::Open { until } if until > now => Err(until - now),
+        State::Open { .. } => {
+            *state = State::HalfOpen { since: now };
+            Ok(())
+        }
+        // the trial connection never reported back, let another one through
+        State::HalfOpen { since } if now - since > open_duration() => {
+            *state = State::HalfOpen { since: now };
+            Ok(())
+        }
+        State::HalfOpen { .. } => Err(Duration::ZERO),
+    }
+}
+
+pub fn is_open(server: &str) -> bool {
+    matches!(
+        BREAKERS.lock().unwrap().get(server),
+        Some(State::Open { until }) if *until > Instant::now()
+    )
+}
+
+pub fn record_success(server: &str) {
+    if let Some(State::HalfOpen { .. } | State::Open { .. }) =
+        BREAKERS.lock().unwrap().remove(server)
+    {
+        info!("Circuit breaker of {} closed again", server);
+    }
+}
+
+pub fn record_failure(server: &str) {
+    let config = &ProxyServer::instance().config().circuit_breaker;
+    if !config.enabled {
+        return;
+    }
+    let mut breakers = BREAKERS.lock().unwrap();
+    let state = breakers
+        .entry(server.to_owned())
+        .or_insert(State::Closed { failures: 0 });
+    let failures = match *state {
+        State::Closed { failures } => failures + 1,
+        State::HalfOpen { .. } => config.failure_threshold,
+        State::Open { .. } => return,
+    };
+    if failures >= config.failure_threshold.max(1) {
+        warn!(
+            "Circuit breaker of {} opened after {} failed connections",
+            server, failures
+        );
+        *state = State::Open {
+            until: Instant::now() + open_duration(),
+        };
+    } else {
+        *state = State::Closed { failures };
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
     chat::*,
     server::{
         brigadier::{Suggestion, Suggestions},
-        ProxyServer,
+        health, ProxyServer, ServerList,
     },
 };
 use crate::server::ProxiedPlayer;
@@ -43,6 +43,14 @@ pub fn register_all(builder: CommandRegistryBuilder) -> CommandRegistryBuilder {
             "crust.command.send",
             "Send players to a different backend",
         )
+        .core_command(
+            ["available"],
+            Default::default(),
+            available_command,
+            Some(available_command_completer),
+            "crust.command.available",
+            "Marks a server as available or queues players connecting to it",
+        )
         .core_command(
             ["glist"],
             Default::default(),
@@ -140,7 +148,10 @@ fn send_command_completer(
     } else if args.len() == 2 {
         let filter = args.get(1).unwrap();
         let block = ProxyServer::instance().servers().blocking_read();
-        let servers = block.servers_by_name.keys();
+        let servers = block
+            .servers_by_name
+            .keys()
+            .chain(block.all_groups().map(|group| &group.name));
         for server_name in servers {
             if !server_name.starts_with(filter) {
                 continue;
@@ -185,8 +196,7 @@ fn send_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
     let player_name = args.first().unwrap();
     let server_name = args.get(1).unwrap().to_string();
     let server_block = ProxyServer::instance().servers().blocking_read();
-    let server_id = server_block.servers_by_name.get(&server_name.to_string());
-    if server_id.is_none() {
+    if !server_block.contains(&server_name) {
         sender.send_message(TextBuilder::new(format!("The server {} was not found", server_name)).style(Style::empty().with_color(TextColor::Red)));
         return;
     }
@@ -246,6 +256,68 @@ fn send_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
     }
 }
 
+fn available_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
+    let available = match args.get(1).map(|arg| arg.to_lowercase()).as_deref() {
+        Some("on") | Some("true") => true,
+        Some("off") | Some("false") => false,
+        _ => {
+            sender.send_message(
+                TextBuilder::new("Usage: /available <server> <on|off>")
+                    .style(Style::empty().with_color(TextColor::Red)),
+            );
+            return;
+        }
+    };
+    let server_name = args.first().unwrap();
+    if !ProxyServer::instance()
+        .servers
+        .blocking_write()
+        .set_available(server_name, available)
+    {
+        sender.send_message(
+            TextBuilder::new(format!("The server {} was not found", server_name))
+                .style(Style::empty().with_color(TextColor::Red)),
+        );
+        return;
+    }
+    let style = Style::default().with_color(TextColor::from_rgb(182, 255, 156));
+    sender.send_message(
+        TextBuilder::new(if available {
+            format!("The server {} is available again", server_name)
+        } else {
+            format!("Players connecting to {} are queued now", server_name)
+        })
+        .style(style),
+    );
+}
+
+fn available_command_completer(
+    _sender: &CommandSender,
+    _name: &str,
+    args: Vec<&str>,
+    suggestions: &mut Suggestions,
+) {
+    let filter = args.last().copied().unwrap_or_default();
+    let names = match args.len() {
+        0 | 1 => ProxyServer::instance()
+            .servers
+            .blocking_read()
+            .all_servers()
+            .map(|(_, info)| info.label.clone())
+            .collect(),
+        2 => vec!["on".to_owned(), "off".to_owned()],
+        _ => Vec::new(),
+    };
+    for name in names {
+        if name.starts_with(filter) {
+            suggestions.matches.push(Suggestion {
+                text: name,
+                tooltip: None,
+            });
+        }
+    }
+}
+
 fn glist_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
     let mut amt = 0usize;
     let mut map = HashMap::new();
@@ -300,20 +372,22 @@ fn server_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
         }
 
         let mut builder = TextBuilder::new("Available servers: ").style(style);
-        for (_, info) in servers.all_servers() {
+        let names = servers
+            .all_servers()
+            .map(|(_, info)| &info.label)
+            .chain(servers.all_groups().map(|group| &group.name));
+        for name in names {
             if first {
                 first = false;
             } else {
                 builder.add_extra(", ");
             }
-            let mut text = Text::new(info.label.as_str());
+            let mut text = Text::new(name.as_str());
             text.click_event = Some(ClickEvent {
                 action: ClickAction::RunCommand,
-                value: format!("/server {}", info.label),
+                value: format!("/server {}", name),
             });
-            text.hover_event = Some(HoverEvent::ShowText(Box::new(Text::new(
-                "click to connect",
-            ))));
+            text.hover_event = Some(HoverEvent::ShowText(Box::new(server_hover(&servers, name))));
             builder.add_extra(text);
         }
         drop(servers);
@@ -321,8 +395,7 @@ fn server_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
     } else {
         let server_name = args.first().unwrap();
         let servers = ProxyServer::instance().servers.blocking_read();
-        let server = servers.get_server_by_name(&server_name);
-        if let Some(server_id) = server {
+        if servers.contains(&server_name) {
             drop(servers);
             ProxyServer::instance().block_on(crate::server::packet_handler::switch_server_helper(
                 player, server_name.to_string(),
@@ -337,6 +410,44 @@ fn server_command(sender: &CommandSender, _name: &str, args: Vec<&str>) {
     }
 }
 
+fn server_hover(servers: &ServerList, name: &str) -> Text {
+    if let Some(group) = servers.get_group(name) {
+        let statuses = group
+            .servers
+            .iter()
+            .filter_map(|server| health::status(server))
+            .collect::<Vec<_>>();
+        let online = statuses.iter().filter(|status| status.online).count();
+        let players = statuses
+            .iter()
+            .filter(|status| status.online)
+            .map(|status| status.players)
+            .sum::<i32>();
+        return Text::new(format!(
+            "§7{}/{} servers online, {} players\n§eclick to connect",
+            online,
+            group.servers.len(),
+            players
+        ));
+    }
+    match health::status(name) {
+        Some(status) if status.online => {
+            let mut hover = Text::new(format!(
+                "§aOnline §7{}/{} players, {}ms, {}\n",
+                status.players, status.max_players, status.latency, status.version
+            ));
+            if !status.motd.get_string().is_empty() {
+                hover.add_extra(status.motd);
+                hover.add_extra("\n");
+            }
+            hover.add_extra("§eclick to connect");
+            hover
+        }
+        Some(_) => Text::new("§cOffline\n§eclick to connect"),
+        None => Text::new("click to connect"),
+    }
+}
+
 fn server_command_completer(
     _sender: &CommandSender,
     _name: &str,
@@ -348,12 +459,16 @@ fn server_command_completer(
     }
     let filter = args.first().unwrap();
     let servers = ProxyServer::instance().servers().blocking_read();
-    for (_, info) in servers.all_servers() {
-        if !info.label.starts_with(filter) {
+    let names = servers
+        .all_servers()
+        .map(|(_, info)| &info.label)
+        .chain(servers.all_groups().map(|group| &group.name));
+    for name in names {
+        if !name.starts_with(filter) {
             continue;
         }
         suggestions.matches.push(Suggestion {
-            text: info.label.clone(),
+            text: name.clone(),
             tooltip: None,
         });
     }
@@ -4,6 +4,8 @@ use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 
 use crate::util::{IOError, IOErrorKind, IOResult, VarInt};
 
+use super::limits::{self, limit_error};
+
 pub fn compress(data: &[u8], threshold: i32, dest: &mut Vec<u8>) -> IOResult<bool> {
     if data.len() < threshold as usize {
         VarInt(0).encode(dest, 5)?; // no compression / uncompressed length
@@ -20,18 +22,54 @@ pub fn compress(data: &[u8], threshold: i32, dest: &mut Vec<u8>) -> IOResult<boo
     }
 }
 
-pub fn decompress(data: &[u8], dest: &mut Vec<u8>) -> IOResult<()> {
+pub fn decompress<W: Write + ?Sized>(data: &[u8], dest: &mut W) -> IOResult<()> {
     let mut reader = Cursor::new(data);
     let uncompressed_length = VarInt::decode(&mut reader, 5)?.get() as usize;
+    let max_size = limits::limits().max_decompressed_size;
     if uncompressed_length == 0 {
-        dest.extend_from_slice(&data[reader.position() as usize..]);
+        dest.write_all(&data[reader.position() as usize..])?;
     } else {
-        let mut decoder = SizeLimitedReader::new(ZlibDecoder::new(reader), 8 * 1024 * 1024);
-        decoder.read_to_end(dest)?;
+        if uncompressed_length > max_size {
+            return Err(limit_error(format!(
+                "Decompressed size of {} bytes exceeds the limit of {} bytes",
+                uncompressed_length, max_size
+            )));
+        }
+        // a frame can not inflate past the size it announced
+        let mut decoder = SizeLimitedReader::new(ZlibDecoder::new(reader), uncompressed_length);
+        let decompressed = std::io::copy(&mut decoder, dest)?;
+        if decompressed != uncompressed_length as u64 {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                format!(
+                    "Decompressed size of {} bytes does not match the declared {} bytes",
+                    decompressed, uncompressed_length
+                ),
+            ));
+        }
     }
     Ok(())
 }
 
+/// Reads the packet id of a compressed frame, only the first bytes of the packet are inflated
+pub fn peek_packet_id(data: &[u8]) -> IOResult<i32> {
+    let mut reader = Cursor::new(data);
+    let uncompressed_length = VarInt::decode(&mut reader, 5)?.get() as usize;
+    if uncompressed_length == 0 {
+        return Ok(VarInt::decode(&mut reader, 5)?.get());
+    }
+    let max_size = limits::limits().max_decompressed_size;
+    if uncompressed_length > max_size {
+        return Err(limit_error(format!(
+            "Decompressed size of {} bytes exceeds the limit of {} bytes",
+            uncompressed_length, max_size
+        )));
+    }
+    let mut head = Vec::with_capacity(5);
+    ZlibDecoder::new(reader).take(5).read_to_end(&mut head)?;
+    Ok(VarInt::decode(&mut Cursor::new(&head), 5)?.get())
+}
+
 pub struct SizeLimitedReader<R: Read> {
     inner: R,
     remaining: usize,
@@ -49,10 +87,11 @@ impl<R: Read> SizeLimitedReader<R> {
 impl<R: Read> Read for SizeLimitedReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
         if self.remaining == 0 {
-            return Err(IOError::new(
-                IOErrorKind::InvalidData,
-                "exceeded length limit",
-            )); // Stop reading if limit is reached
+            // ending exactly at the limit is fine
+            return match self.inner.read(&mut [0])? {
+                0 => Ok(0),
+                _ => Err(limit_error("exceeded length limit")),
+            };
         }
 
         let max_read = std::cmp::min(self.remaining, buf.len());
@@ -79,10 +118,7 @@ impl<'a, R: Read + ?Sized> RefSizeLimitedReader<'a, R> {
 impl<'a, R: Read + ?Sized> Read for RefSizeLimitedReader<'a, R> {
     fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
         if self.remaining == 0 {
-            return Err(IOError::new(
-                IOErrorKind::InvalidData,
-                "exceeded length limit",
-            )); // Stop reading if limit is reached
+            return Err(limit_error("exceeded length limit")); // Stop reading if limit is reached
         }
 
         let max_read = std::cmp::min(self.remaining, buf.len());
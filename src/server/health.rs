@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    chat::{deserialize_json, Text},
+    haproxy::{HAProxyMessage, HAProxyMessageV2},
+    util::{EncodingHelper, IOError, IOErrorKind, IOResult, VarInt},
+    version::SUPPORTED_VERSIONS,
+};
+
+use super::{
+    network::{self, Stream},
+    packets::{
+        self, encode_and_send_packet, read_and_decode_packet, Handshake, ProtocolState,
+        PROTOCOL_STATE_STATUS,
+    },
+    ProxyServer,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthCheckConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Milliseconds between two status pings of a server
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Milliseconds until a server that does not answer the ping counts as offline
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_interval() -> u64 {
+    5000
+}
+
+fn default_timeout() -> u64 {
+    3000
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            interval: default_interval(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+/// Result of the last status ping, offline servers keep the values of their last successful ping
+#[derive(Debug, Clone)]
+pub struct ServerStatus {
+    pub online: bool,
+    /// round trip of the ping packet in milliseconds
+    pub latency: u32,
+    pub players: i32,
+    pub max_players: i32,
+    /// the description as sent by the server
+    pub motd: Text,
+    pub version: String,
+}
+
+lazy_static! {
+    static ref STATUS: RwLock<HashMap<String, ServerStatus>> = RwLock::new(HashMap::new());
+}
+
+pub fn status(server: &str) -> Option<ServerStatus> {
+    STATUS.read().unwrap().get(server).cloned()
+}
+
+/// Servers that were not checked yet are assumed to be healthy
+pub fn is_healthy(server: &str) -> bool {
+    STATUS
+        .read()
+        .unwrap()
+        .get(server)
+        .is_none_or(|status| status.online)
+}
+
+pub fn start() {
+    let config = &ProxyServer::instance().config().health_check;
+    if !config.enabled {
+        return;
+    }
+    let interval = Duration::from_millis(config.interval.max(1000));
+    let timeout = Duration::from_millis(config.timeout.max(100));
+    ProxyServer::instance().spawn_task(async move {
+        loop {
+            let servers = ProxyServer::instance()
+                .servers()
+                .read()
+                .await
+                .list_servers()
+                .map(|info| (info.label.clone(), info.address.clone(), info.proxy_protocol))
+                .collect::<Vec<_>>();
+            STATUS
+                .write()
+                .unwrap()
+                .retain(|label, _| servers.iter().any(|(l, _, _)| l == label));
+            let checks = servers
+                .into_iter()
+                .map(|(label, address, proxy_protocol)| {
+                    tokio::spawn(async move {
+                        let result =
+                            tokio::time::timeout(timeout, ping(&address, proxy_protocol)).await;
+                        update(label, result.unwrap_or_else(|_| {
+                            Err(IOError::new(IOErrorKind::TimedOut, "Status ping timed out"))
+                        }));
+                    })
+                })
+                .collect::<Vec<_>>();
+            for check in checks {
+                check.await.ok();
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+fn update(label: String, result: IOResult<ServerStatus>) {
+    let mut statuses = STATUS.write().unwrap();
+    let was_online = statuses.get(&label).map(|status| status.online);
+    match result {
+        Ok(status) => {
+            if was_online == Some(false) {
+                info!("Server {} is online again", label);
+            }
+            statuses.insert(label, status);
+        }
+        Err(e) => {
+            if was_online != Some(false) {
+                info!("Server {} is offline: {}", label, e);
+            } else {
+                debug!("Server {} is still offline: {}", label, e);
+            }
+            let status = statuses.entry(label).or_insert_with(|| ServerStatus {
+                online: false,
+                latency: 0,
+                players: 0,
+                max_players: 0,
+                motd: Text::new(""),
+                version: String::new(),
+            });
+            status.online = false;
+        }
+    }
+}
+
+/// Sends a status request and a ping to the server like a client in the server list does
+pub async fn ping(address: &str, proxy_protocol: bool) -> IOResult<ServerStatus> {
+    let version = *SUPPORTED_VERSIONS.last().unwrap();
+    let (response, latency) = request_status(address, proxy_protocol, version).await?;
+    Ok(ServerStatus {
+        online: true,
+        latency,
+        players: response["players"]["online"].as_i64().unwrap_or(0) as i32,
+        max_players: response["players"]["max"].as_i64().unwrap_or(0) as i32,
+        motd: deserialize_json(&response["description"]).unwrap_or_else(|_| Text::new("")),
+        version: response["version"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned(),
+    })
+}
+
+/// Connects to the server for a check of the proxy itself, a backend with the proxy protocol gets a LOCAL header
+pub async fn connect(address: &str, proxy_protocol: bool) -> IOResult<Stream> {
+    let mut stream = network::connect(address).await?;
+    if proxy_protocol {
+        HAProxyMessage::from(HAProxyMessageV2::local())
+            .write_to(&mut stream)
+            .await?;
+    }
+    Ok(stream)
+}
+
+/// The raw status response of the server and the round trip of the ping in milliseconds
+pub async fn request_status(
+    address: &str,
+    proxy_protocol: bool,
+    version: i32,
+) -> IOResult<(Value, u32)> {
+    let mut stream = connect(address, proxy_protocol).await?;
+    let (host, port) = match address.rsplit_once(':') {
+        _ if address.starts_with(network::UNIX_PREFIX) => ("localhost", 25565),
+        Some((host, port)) => (host, port.parse().unwrap_or(25565)),
+        None => (address, 25565),
+    };
+    let mut write_buf = Vec::new();
+    let mut protocol_buf = Vec::new();
+    let mut read_buf = Vec::new();
+
+    packets::get_full_client_packet_buf_write_buffer(
+        &mut write_buf,
+        &Handshake {
+            version,
+            host: host.to_owned(),
+            port,
+            next_state: PROTOCOL_STATE_STATUS,
+        },
+        version,
+        ProtocolState::Handshake,
+    )?;
+    encode_and_send_packet(&mut stream, &write_buf, &mut protocol_buf, -1, &mut None).await?;
+    // status request
+    encode_and_send_packet(&mut stream, &[0], &mut protocol_buf, -1, &mut None).await?;
+    read_and_decode_packet(&mut stream, &mut read_buf, &mut protocol_buf, -1, &mut None).await?;
+    let mut reader = Cursor::new(&read_buf);
+    if VarInt::decode_simple(&mut reader)?.get() != 0 {
+        return Err(IOError::new(IOErrorKind::InvalidData, "Bad status response packet id"));
+    }
+    let response: Value = serde_json::from_str(&EncodingHelper::read_string(&mut reader, 32767)?)?;
+
+    let sent = Instant::now();
+    write_buf.clear();
+    VarInt(1).encode_simple(&mut write_buf)?;
+    write_buf.write_i64::<BE>(0)?;
+    encode_and_send_packet(&mut stream, &write_buf, &mut protocol_buf, -1, &mut None).await?;
+    read_and_decode_packet(&mut stream, &mut read_buf, &mut protocol_buf, -1, &mut None).await?;
+    let latency = sent.elapsed().as_millis() as u32;
+    let mut reader = Cursor::new(&read_buf);
+    if VarInt::decode_simple(&mut reader)?.get() != 1 {
+        return Err(IOError::new(IOErrorKind::InvalidData, "Bad pong packet id"));
+    }
+    reader.read_i64::<BE>()?;
+
+    Ok((response, latency))
+}
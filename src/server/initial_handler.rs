@@ -18,16 +18,17 @@ use crate::{
     },
     server::{
         packet_ids::{ClientPacketType, PacketRegistry},
-        packets::{read_and_decode_packet, EncryptionResponse, Packet, ProtocolState},
+        packets::{read_and_decode_packet_limited, EncryptionResponse, Packet, ProtocolState},
     },
     util::{EncodingHelper, IOError, IOErrorKind, IOResult, VarInt},
 };
 
 use super::{
     encryption::*,
+    limits,
     packets::{
         self, encode_and_send_packet, EncryptionRequest, Handshake, LoginDisconnect, LoginRequest,
-        LoginSuccess, PROTOCOL_READ_TIMEOUT, PROTOCOL_STATE_LOGIN, PROTOCOL_STATE_STATUS,
+        LoginSuccess, PROTOCOL_STATE_LOGIN, PROTOCOL_STATE_STATUS,
         PROTOCOL_STATE_TRANSFER,
     },
     proxy_handler::ProxyingData,
@@ -36,7 +37,7 @@ use super::{
 
 macro_rules! check_timeout {
     ($fut:expr) => {
-        tokio::time::timeout(PROTOCOL_READ_TIMEOUT, $fut)
+        tokio::time::timeout(limits::limits().handshake_timeout(), $fut)
     };
 }
 
@@ -51,14 +52,25 @@ async fn read_ha_proxy(stream: &mut TcpStream) -> IOResult<HAProxyMessage> {
 
 pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
     tokio::spawn(async move {
+        let mut local_addr = match stream.local_addr() {
+            Ok(local_addr) => local_addr,
+            Err(e) => {
+                log::debug!("[{}] Failed to get the local address: {}", peer_addr, e);
+                return;
+            }
+        };
         if ProxyServer::instance().config().proxy_protocol {
             match read_ha_proxy(&mut stream).await {
                 Ok(packet) => {
-                    let new_addr = match packet {
+                    let new_addrs = match packet {
                         HAProxyMessage::V1(HAProxyMessageV1 { protocol_family }) => {
                             match protocol_family {
-                                HAProxyProtocolFamily::TCP4 { src, .. } => Some(src.into()),
-                                HAProxyProtocolFamily::TCP6 { src, .. } => Some(src.into()),
+                                HAProxyProtocolFamily::TCP4 { src, dst } => {
+                                    Some((src.into(), dst.into()))
+                                }
+                                HAProxyProtocolFamily::TCP6 { src, dst } => {
+                                    Some((src.into(), dst.into()))
+                                }
                                 HAProxyProtocolFamily::Unknown => None,
                             }
                         }
@@ -69,8 +81,12 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
                                 None
                             } else {
                                 match addresses {
-                                    HAProxyAdresses::Inet { src, .. } => Some(src.into()),
-                                    HAProxyAdresses::Inet6 { src, .. } => Some(src.into()),
+                                    HAProxyAdresses::Inet { src, dst } => {
+                                        Some((src.into(), dst.into()))
+                                    }
+                                    HAProxyAdresses::Inet6 { src, dst } => {
+                                        Some((src.into(), dst.into()))
+                                    }
                                     _ => {
                                         log::debug!("[{}] HAProxy protocol failed to decode: Unsupported address family", peer_addr);
                                         return;
@@ -79,9 +95,11 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
                             }
                         }
                     };
-                    if let Some(new_addr) = new_addr {
+                    if let Some((new_addr, new_local_addr)) = new_addrs {
                         log::debug!("Changed remote address {} to {}", peer_addr, new_addr);
                         peer_addr = new_addr;
+                        // the address the client connected to in front of the load balancer
+                        local_addr = new_local_addr;
                     }
                 }
                 Err(e) => {
@@ -94,7 +112,11 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
             let mut buffer = Vec::new();
             let handshake = match handshaking(&mut stream, &mut buffer).await {
                 Err(e) => {
-                    log::debug!("[{}] Handshake state failed: {}", peer_addr, e);
+                    if limits::is_limit_error(&e) {
+                        log::warn!("[{}] Handshake rejected: {}", peer_addr, e);
+                    } else {
+                        log::debug!("[{}] Handshake state failed: {}", peer_addr, e);
+                    }
                     return;
                 }
                 Ok(handshake) => handshake,
@@ -103,12 +125,36 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
 
             match handshake.next_state {
                 PROTOCOL_STATE_STATUS => {
-                    if let Err(e) = handle_status(stream, handshake.version).await {
+                    if !super::status::check_rate_limit(peer_addr.ip()) {
+                        log::debug!("[{}] Status request rate limited", peer_addr);
+                        return;
+                    }
+                    super::anti_bot::record_ping(peer_addr.ip());
+                    if let Err(e) = handle_status(stream, &handshake).await {
                         log::debug!("[{}] Status state failed: {}", peer_addr, e);
                     }
                     return;
                 }
                 PROTOCOL_STATE_LOGIN | PROTOCOL_STATE_TRANSFER => {
+                    super::anti_bot::record_join();
+                    if !super::throttle::check_login(peer_addr.ip()) {
+                        log::debug!("[{}] Login throttled", peer_addr);
+                        let message =
+                            &ProxyServer::instance().config().connection_throttle.kick_message;
+                        if !message.is_empty() {
+                            send_login_disconnect(
+                                &mut stream,
+                                &mut buffer,
+                                Text::new(message.replace('&', "§")),
+                                handshake.version,
+                                -1,
+                                &mut None,
+                            )
+                            .await
+                            .ok();
+                        }
+                        return;
+                    }
                     if ProxyServer::instance().player_count as isize
                         >= ProxyServer::instance().config.max_players as isize
                     {
@@ -125,10 +171,16 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
                         return;
                     }
 
-                    match handle_login(&mut stream, handshake, &mut buffer, peer_addr).await {
+                    let login =
+                        handle_login(&mut stream, handshake, &mut buffer, peer_addr, local_addr);
+                    match login.await {
                         Ok(state) => state,
                         Err(e) => {
-                            log::debug!("[{}] Login state failed: {}", peer_addr, e);
+                            if limits::is_limit_error(&e) {
+                                log::warn!("[{}] Login packet rejected: {}", peer_addr, e);
+                            } else {
+                                log::debug!("[{}] Login state failed: {}", peer_addr, e);
+                            }
                             return;
                         }
                     }
@@ -143,7 +195,9 @@ pub async fn handle(mut stream: TcpStream, mut peer_addr: SocketAddr) {
     });
 }
 
-async fn handle_status(mut stream: TcpStream, version: i32) -> IOResult<()> {
+async fn handle_status(mut stream: TcpStream, handshake: &Handshake) -> IOResult<()> {
+    let version = handshake.version;
+    let virtual_host = virtual_host(handshake);
     let mut state = 1;
     let mut write_buf = Vec::new();
     loop {
@@ -166,7 +220,7 @@ async fn handle_status(mut stream: TcpStream, version: i32) -> IOResult<()> {
                 VarInt(0).encode(&mut write_buf, 5)?; // packet id
                 EncodingHelper::write_string(
                     &mut write_buf,
-                    &super::status::get_status_response(version).to_string(),
+                    &super::status::status_response_json(version, &virtual_host).await,
                 )?; // response
 
                 VarInt(write_buf.len() as i32)
@@ -203,16 +257,34 @@ async fn handle_status(mut stream: TcpStream, version: i32) -> IOResult<()> {
     Ok(())
 }
 
+/// The host the client connected to without forge markers, used for the forced hosts
+fn virtual_host(handshake: &Handshake) -> String {
+    handshake
+        .host
+        .split('\0')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
 async fn handshaking(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> IOResult<Handshake> {
     let handshake_length = check_timeout!(VarInt::decode_async(stream, 3))
         .await??
         .get();
-    if handshake_length < 6 || handshake_length > 1000 {
+    if handshake_length < 6 {
         return Err(IOError::new(
             IOErrorKind::InvalidData,
             "Bad handshake length",
         ));
     }
+    let max_size = limits::limits().max_handshake_size;
+    if handshake_length as usize > max_size {
+        return Err(limits::limit_error(format!(
+            "Handshake of {} bytes exceeds the limit of {} bytes",
+            handshake_length, max_size
+        )));
+    }
     buffer.resize(handshake_length as usize, 0);
     check_timeout!(stream.read_exact(buffer)).await??;
     let mut reader = Cursor::new(&*buffer);
@@ -277,6 +349,7 @@ async fn handle_login(
     handshake: Handshake,
     buffer: &mut Vec<u8>,
     address: SocketAddr,
+    local_address: SocketAddr,
 ) -> IOResult<ProxyingData> {
     #[derive(Debug, PartialEq, Eq)]
     enum LoginState {
@@ -288,6 +361,7 @@ async fn handle_login(
     let mut protocol_buf = Vec::new();
 
     let version = handshake.version;
+    let virtual_host = virtual_host(&handshake);
     let mut login_state = LoginState::Request;
     let mut login_request = None;
     let mut compression_threshold = -1;
@@ -298,12 +372,13 @@ async fn handle_login(
     let mut profile = None;
     loop {
         buffer.clear();
-        read_and_decode_packet(
+        read_and_decode_packet_limited(
             stream,
             buffer,
             &mut protocol_buf,
             compression_threshold,
             &mut decryption,
+            limits::limits().max_frame_size(ProtocolState::Login),
         )
         .await?;
 
@@ -340,6 +415,23 @@ async fn handle_login(
                         .ok();
                         return Err(IOError::new(IOErrorKind::InvalidData, "Bad username"));
                     }
+                    if let Err(message) = super::anti_bot::check_login(address.ip(), &request.name)
+                    {
+                        send_login_disconnect(
+                            stream,
+                            buffers.write_buf,
+                            Text::new(message),
+                            version,
+                            compression_threshold,
+                            &mut encryption,
+                        )
+                        .await
+                        .ok();
+                        return Err(IOError::new(
+                            IOErrorKind::Other,
+                            "Blocked by the bot protection",
+                        ));
+                    }
                     let cfg = ProxyServer::instance().config();
                     if cfg.offline_mode_encryption || cfg.online_mode {
                         if !cfg.online_mode {
@@ -492,6 +584,8 @@ async fn handle_login(
                             format!("Received login acknowledge in {:?} state", login_state),
                         ));
                     }
+                    let login_request = login_request.unwrap();
+                    super::anti_bot::remember_name(&login_request.name);
                     return Ok(ProxyingData {
                         version,
                         login_result: profile.unwrap(),
@@ -500,9 +594,11 @@ async fn handle_login(
                             Some(encryption) => Some((encryption, decryption.unwrap())),
                             None => None,
                         },
-                        player_public_key: login_request.unwrap().public_key,
+                        player_public_key: login_request.public_key,
                         protocol_state: ProtocolState::Config,
                         address,
+                        local_address,
+                        virtual_host,
                     });
                 }
                 ClientPacketType::CookieResponse => {
@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    ops::DerefMut,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    chat::Text,
+    util::{EncodingHelper, Handle, IOError, IOErrorKind, IOResult, VarInt, WeakHandle},
+    version::{R1_20_3, R1_20_5, R1_21_2},
+};
+
+use super::{
+    nbt,
+    limits,
+    packet_handler::ClientPacketHandler,
+    packet_ids::ServerPacketType,
+    packet_limiter::{PacketLimiter, Verdict},
+    packets::{
+        read_and_decode_packet_limited, GameEvent, JoinGame, ProtocolState, ServerKeepAlive,
+        SynchronizePlayerPosition, GAME_EVENT_START_WAITING_FOR_CHUNKS,
+    },
+    proxy_handler::ConnectionHandle,
+    circuit_breaker, health, server_group, tab_list, ProxiedPlayer, ProxyServer,
+};
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const DIMENSION: &str = "minecraft:overworld";
+const ENTITY_ID: i32 = 0;
+/// above the build height, the client does not wait for chunks there
+const SPAWN_Y: f64 = 1000.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimboConfig {
+    /// Keeps players without a server in an empty world instead of kicking them
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Milliseconds between attempts to connect the players in the limbo to a priority server
+    #[serde(default = "default_retry_interval")]
+    pub retry_interval: u64,
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_retry_interval() -> u64 {
+    5000
+}
+
+fn default_message() -> String {
+    "&cThe server is not available right now, you will be connected as soon as it is back.".to_owned()
+}
+
+impl Default for LimboConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            retry_interval: default_retry_interval(),
+            message: default_message(),
+        }
+    }
+}
+
+/// The registries, tags and feature flags a backend sent while configuring a client
+pub struct LimboWorld {
+    packets: Vec<Vec<u8>>,
+    dimension_type: i32,
+}
+
+#[derive(Default)]
+pub struct LimboCapture {
+    packets: Vec<Vec<u8>>,
+    dimension_type: i32,
+}
+
+lazy_static! {
+    /// The proxy has no registries of its own, the first complete set of each version is reused
+    static ref WORLDS: RwLock<HashMap<i32, Arc<LimboWorld>>> = RwLock::new(HashMap::new());
+}
+
+pub fn is_enabled() -> bool {
+    ProxyServer::instance().config().limbo.enabled
+}
+
+/// Records a config packet of the backend until a world for the version of the player is known
+pub(crate) async fn capture(
+    player: &ProxiedPlayer,
+    packet_type: ServerPacketType,
+    packet_id: i32,
+    buffer: &[u8],
+) -> IOResult<()> {
+    let version = player.protocol_version;
+    if !is_enabled() || WORLDS.read().await.contains_key(&version) {
+        return Ok(());
+    }
+    let mut capture = player.sync_data.limbo_capture.lock().await;
+    if packet_type == ServerPacketType::RegistryData && version >= R1_20_5 {
+        if let Some(index) = overworld_index(buffer, version)? {
+            capture.dimension_type = index;
+        }
+    }
+    let mut packet = Vec::with_capacity(buffer.len() + 5);
+    VarInt(packet_id).encode_simple(&mut packet)?;
+    packet.extend_from_slice(buffer);
+    capture.packets.push(packet);
+    Ok(())
+}
+
+/// Called when the backend finished the configuration, the captured packets are complete now
+pub(crate) async fn finish_capture(player: &ProxiedPlayer) {
+    let capture = std::mem::take(player.sync_data.limbo_capture.lock().await.deref_mut());
+    if capture.packets.is_empty() {
+        return;
+    }
+    WORLDS
+        .write()
+        .await
+        .entry(player.protocol_version)
+        .or_insert_with(|| {
+            Arc::new(LimboWorld {
+                packets: capture.packets,
+                dimension_type: capture.dimension_type,
+            })
+        });
+}
+
+/// Since 1.20.5 the dimension type is sent as index into the registry
+fn overworld_index(buffer: &[u8], version: i32) -> IOResult<Option<i32>> {
+    let mut src = Cursor::new(buffer);
+    if EncodingHelper::read_string(&mut src, 32767)? != "minecraft:dimension_type" {
+        return Ok(None);
+    }
+    let count = VarInt::decode_simple(&mut src)?.get();
+    for index in 0..count {
+        if EncodingHelper::read_string(&mut src, 32767)? == DIMENSION {
+            return Ok(Some(index));
+        }
+        if src.read_u8()? != 0 {
+            nbt::read_networking_nbt(&mut src, version)?;
+        }
+    }
+    Ok(None)
+}
+
+/// Moves the player into the limbo, they are connected to a priority server as soon as one is reachable
+pub fn enter(player: Handle<ProxiedPlayer>) {
+    tokio::spawn(async move {
+        if let Err(e) = run(player.clone()).await {
+            player.client_handle.disconnect(&e.to_string()).await;
+        }
+    });
+}
+
+async fn run(mut player: Handle<ProxiedPlayer>) -> IOResult<()> {
+    if player.client_handle.closed.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut switch_lock = player.sync_data.is_switching_server.lock().await;
+    if *switch_lock || player.sync_data.in_limbo.swap(true, Ordering::Relaxed) {
+        // the player is already on the way to another server
+        return Ok(());
+    }
+    *switch_lock = true;
+    drop(switch_lock);
+
+    let version = player.protocol_version;
+    let state = player.client_handle.protocol_state();
+    info!("[{}] Entering the limbo", player.name);
+
+    // a client that was configured by a backend can't get the registries a second time
+    let world = if state == ProtocolState::Game || player.current_server.is_none() {
+        WORLDS.read().await.get(&version).cloned()
+    } else {
+        None
+    };
+    let config = &ProxyServer::instance().config().limbo;
+    let world = match world {
+        Some(world) => world,
+        None => {
+            // without registries the client can't be moved into a world and would time out in the config state
+            debug!("[{}] No limbo world for version {}", player.name, version);
+            player.sync_data.in_limbo.store(false, Ordering::Relaxed);
+            *player.sync_data.is_switching_server.lock().await = false;
+            return player.kick(Text::new(config.message.replace('&', "§"))).await;
+        }
+    };
+    player.current_server = None;
+    player.server_handle = None;
+    *player.sync_data.limbo_capture.lock().await = LimboCapture::default();
+
+    if let Some(read_task) = player.client_handle.read_task.lock().await.take() {
+        read_task.abort();
+    }
+    let read_task = tokio::spawn(read_task(
+        player.downgrade(),
+        player.client_handle.clone(),
+        version,
+    ));
+    player
+        .client_handle
+        .read_task
+        .lock()
+        .await
+        .replace(read_task.abort_handle());
+
+    let joined = join_world(&player, &world, state).await;
+    *player.sync_data.is_switching_server.lock().await = false;
+    joined?;
+    tab_list::request_update();
+
+    let message = Text::new(config.message.replace('&', "§"));
+    player.send_message(message.clone()).await?;
+
+    let retry_interval = Duration::from_millis(config.retry_interval.max(1000));
+    let mut last_retry = Instant::now();
+    while !player.client_handle.closed.load(Ordering::Relaxed)
+        && player.sync_data.in_limbo.load(Ordering::Relaxed)
+    {
+        tokio::time::sleep(retry_interval.min(KEEP_ALIVE_INTERVAL)).await;
+        {
+            // packets must not be queued once a server switch has started
+            let switch_lock = player.sync_data.is_switching_server.lock().await;
+            if *switch_lock || !player.sync_data.in_limbo.load(Ordering::Relaxed) {
+                continue;
+            }
+            // the keep-alive is registered for the config and game state, the action bar only for the game
+            let id = rand::random();
+            *player.sync_data.pending_keep_alive.lock().await = Some((id, Instant::now()));
+            player.send_packet(&ServerKeepAlive { id }).await?;
+            if player.client_handle.protocol_state() == ProtocolState::Game {
+                player.send_action_bar(message.clone()).await?;
+            }
+        }
+        if last_retry.elapsed() >= retry_interval {
+            if connect_priority_server(&player).await {
+                break;
+            }
+            last_retry = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+async fn wait_for_ack(notified: impl std::future::Future<Output = ()>) -> IOResult<()> {
+    tokio::time::timeout(ACK_TIMEOUT, notified)
+        .await
+        .map_err(|_| IOError::new(IOErrorKind::TimedOut, "Client did not acknowledge the limbo"))
+}
+
+/// Sends the client through the config state into an empty world
+async fn join_world(
+    player: &Handle<ProxiedPlayer>,
+    world: &LimboWorld,
+    state: ProtocolState,
+) -> IOResult<()> {
+    let version = player.protocol_version;
+    let client_handle = &player.client_handle;
+    if state == ProtocolState::Game {
+        client_handle.goto_config(version).await?;
+        wait_for_ack(player.sync_data.config_ack_notify.notified()).await?;
+        player.sync_data.tab_list.lock().await.clear();
+    }
+    for packet in &world.packets {
+        client_handle.queue_packet(packet.clone(), false).await?;
+    }
+    client_handle.goto_game(version).await?;
+    wait_for_ack(player.sync_data.game_ack_notify.notified()).await?;
+
+    player
+        .send_packet(&JoinGame {
+            entity_id: ENTITY_ID,
+            data: login_data(version, world.dimension_type)?,
+        })
+        .await?;
+    player.sync_data.entity_id.store(ENTITY_ID, Ordering::Relaxed);
+    if version >= R1_20_3 {
+        player
+            .send_packet(&GameEvent {
+                event: GAME_EVENT_START_WAITING_FOR_CHUNKS,
+                value: 0.0,
+            })
+            .await?;
+    }
+    player
+        .send_packet(&SynchronizePlayerPosition {
+            teleport_id: 0,
+            x: 0.0,
+            y: SPAWN_Y,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        })
+        .await?;
+    player.resend_boss_bars().await
+}
+
+/// Everything of the login packet after the entity id, the player is a spectator in a flat overworld
+fn login_data(version: i32, dimension_type: i32) -> IOResult<Vec<u8>> {
+    let mut data = Vec::new();
+    data.write_u8(0)?; // hardcore
+    VarInt(1).encode_simple(&mut data)?;
+    EncodingHelper::write_string(&mut data, DIMENSION)?;
+    VarInt(ProxyServer::instance().config().max_players).encode_simple(&mut data)?;
+    VarInt(2).encode_simple(&mut data)?; // view distance
+    VarInt(2).encode_simple(&mut data)?; // simulation distance
+    data.write_u8(0)?; // reduced debug info
+    data.write_u8(1)?; // respawn screen
+    data.write_u8(0)?; // limited crafting
+    if version >= R1_20_5 {
+        VarInt(dimension_type).encode_simple(&mut data)?;
+    } else {
+        EncodingHelper::write_string(&mut data, DIMENSION)?;
+    }
+    EncodingHelper::write_string(&mut data, DIMENSION)?;
+    data.write_i64::<BE>(0)?; // hashed seed
+    data.write_u8(3)?; // spectator
+    data.write_i8(-1)?; // previous game mode
+    data.write_u8(0)?; // debug world
+    data.write_u8(1)?; // flat world
+    data.write_u8(0)?; // death location
+    VarInt(0).encode_simple(&mut data)?; // portal cooldown
+    if version >= R1_21_2 {
+        VarInt(63).encode_simple(&mut data)?; // sea level
+    }
+    if version >= R1_20_5 {
+        data.write_u8(0)?; // enforces secure chat
+    }
+    Ok(data)
+}
+
+/// Tries the priority servers that accept connections, returns true if the player left the limbo
+async fn connect_priority_server(player: &Handle<ProxiedPlayer>) -> bool {
+    let priorities = match ProxyServer::instance()
+        .config()
+        .forced_hosts
+        .get(&player.virtual_host)
+    {
+        Some(host) if !host.priorities.is_empty() => host.priorities.clone(),
+        _ => ProxyServer::instance().servers().read().await.get_priorities().to_vec(),
+    };
+    for name in priorities {
+        let info = match server_group::resolve(&name).await {
+            Some(info) if health::is_healthy(&info.label) && !circuit_breaker::is_open(&info.label) => {
+                info
+            }
+            _ => continue,
+        };
+        // only a reachable server gets a login attempt, failed attempts are reported to the player
+        if !matches!(
+            tokio::time::timeout(
+                PROBE_TIMEOUT,
+                health::connect(&info.address, info.proxy_protocol)
+            )
+            .await,
+            Ok(Ok(_))
+        ) {
+            continue;
+        }
+        debug!("[{}] Leaving the limbo to {}", player.name, info.label);
+        if let Some(switch) = ProxiedPlayer::switch_server(player.clone(), info.label).await {
+            if let Ok(true) = switch.await {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Reads the packets of a player in the limbo, nothing is forwarded but proxy commands still work
+async fn read_task(player: WeakHandle<ProxiedPlayer>, client_handle: ConnectionHandle, version: i32) {
+    let mut read_buf = Vec::new();
+    let mut protocol_buf = Vec::new();
+    let mut read = client_handle.reader.lock().await;
+    let mut decryption = client_handle.decryption.lock().await;
+    let mut limiter = PacketLimiter::new();
+    let display_name = format!(
+        "[{} - limbo]",
+        player
+            .upgrade()
+            .map(|player| player.name.clone())
+            .unwrap_or_default()
+    );
+    loop {
+        let state = client_handle.protocol_state();
+        if let Err(e) = read_and_decode_packet_limited(
+            read.deref_mut(),
+            &mut read_buf,
+            &mut protocol_buf,
+            client_handle.compression_threshold,
+            decryption.deref_mut(),
+            limits::limits().max_frame_size(state),
+        )
+        .await
+        {
+            limits::log_violation(&display_name, state, version, None, &e);
+            client_handle.disconnect(&e.to_string()).await;
+            break;
+        }
+        let packet_id = match VarInt::decode_simple(&mut Cursor::new(&read_buf)) {
+            Ok(packet_id) => packet_id.get(),
+            Err(e) => {
+                client_handle.disconnect(&e.to_string()).await;
+                break;
+            }
+        };
+        match limiter.check(
+            &display_name,
+            state,
+            version,
+            packet_id,
+            read_buf.len(),
+        ) {
+            Verdict::Allow => {}
+            Verdict::Drop => continue,
+            Verdict::Kick(reason) => {
+                client_handle.disconnect(&reason).await;
+                break;
+            }
+        }
+        if let Err(e) = ClientPacketHandler::handle_packet(
+            packet_id,
+            &read_buf[VarInt::get_size(packet_id)..],
+            version,
+            &player,
+            &client_handle,
+        )
+        .await
+        {
+            limits::log_violation(&display_name, state, version, Some(packet_id), &e);
+            client_handle.disconnect(&e.to_string()).await;
+            break;
+        }
+    }
+}
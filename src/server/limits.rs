@@ -0,0 +1,173 @@
+use std::{collections::HashMap, fmt::Display, time::Duration};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::util::{IOError, IOErrorKind};
+
+use super::{packet_ids::PacketRegistry, packets::ProtocolState, ProxyServer};
+
+/// The largest frame a 3 byte length prefix can announce
+pub const MAX_FRAME_SIZE: usize = 2097151;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrameSizeLimits {
+    pub login: usize,
+    pub config: usize,
+    pub game: usize,
+}
+
+impl Default for FrameSizeLimits {
+    fn default() -> Self {
+        Self {
+            login: 1048576,
+            config: MAX_FRAME_SIZE,
+            game: MAX_FRAME_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// Milliseconds until a connection that sends no packet is closed
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// Milliseconds a new connection has to send the proxy protocol header and the handshake
+    #[serde(default = "default_handshake_timeout")]
+    pub handshake_timeout: u64,
+    #[serde(default = "default_max_handshake_size")]
+    pub max_handshake_size: usize,
+    /// Sizes of the compressed frames clients may send in each protocol state
+    #[serde(default)]
+    pub max_frame_size: FrameSizeLimits,
+    /// Size a compressed packet may have once it is decompressed
+    #[serde(default = "default_max_decompressed_size")]
+    pub max_decompressed_size: usize,
+    /// NBT limits apply to everything the proxy decodes, also to backend data like text components
+    /// and the registries captured for the limbo, so they must not be lower than what backends send
+    #[serde(default = "default_max_nbt_depth")]
+    pub max_nbt_depth: u16,
+    #[serde(default = "default_max_nbt_bytes")]
+    pub max_nbt_bytes: u64,
+    /// Data of custom payloads sent by clients, the protocol does not allow more than 65535 bytes
+    #[serde(default = "default_max_custom_payload_size")]
+    pub max_custom_payload_size: usize,
+    /// Overrides ``max_custom_payload_size`` for single channels
+    #[serde(default)]
+    pub custom_payload_sizes: HashMap<String, usize>,
+}
+
+fn default_read_timeout() -> u64 {
+    30000
+}
+
+fn default_handshake_timeout() -> u64 {
+    30000
+}
+
+fn default_max_handshake_size() -> usize {
+    1000
+}
+
+fn default_max_decompressed_size() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_max_nbt_depth() -> u16 {
+    512
+}
+
+fn default_max_nbt_bytes() -> u64 {
+    2097152
+}
+
+fn default_max_custom_payload_size() -> usize {
+    32767
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            read_timeout: default_read_timeout(),
+            handshake_timeout: default_handshake_timeout(),
+            max_handshake_size: default_max_handshake_size(),
+            max_frame_size: FrameSizeLimits::default(),
+            max_decompressed_size: default_max_decompressed_size(),
+            max_nbt_depth: default_max_nbt_depth(),
+            max_nbt_bytes: default_max_nbt_bytes(),
+            max_custom_payload_size: default_max_custom_payload_size(),
+            custom_payload_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.read_timeout)
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_millis(self.handshake_timeout)
+    }
+
+    pub fn max_frame_size(&self, state: ProtocolState) -> usize {
+        match state {
+            ProtocolState::Login => self.max_frame_size.login,
+            ProtocolState::Config => self.max_frame_size.config,
+            ProtocolState::Game => self.max_frame_size.game,
+            _ => MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn max_custom_payload_size(&self, channel: &str) -> usize {
+        self.custom_payload_sizes
+            .get(channel)
+            .copied()
+            .unwrap_or(self.max_custom_payload_size)
+    }
+}
+
+pub fn limits() -> &'static LimitsConfig {
+    &ProxyServer::instance().config().limits
+}
+
+/// Error of data that exceeds a configured limit, read tasks log these
+#[derive(Debug)]
+pub struct LimitExceeded(pub String);
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+pub fn limit_error<S: Into<String>>(message: S) -> IOError {
+    IOError::new(IOErrorKind::InvalidData, LimitExceeded(message.into()))
+}
+
+pub fn is_limit_error(error: &IOError) -> bool {
+    error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<LimitExceeded>())
+}
+
+/// Logs the error of a client packet if it is a limit violation, the packet id is unknown for rejected frames
+pub fn log_violation(
+    name: &str,
+    state: ProtocolState,
+    version: i32,
+    packet_id: Option<i32>,
+    error: &IOError,
+) {
+    if !is_limit_error(error) {
+        return;
+    }
+    match packet_id
+        .and_then(|id| PacketRegistry::instance().get_client_packet_type(state, version, id))
+    {
+        Some(packet_type) => warn!("{} {:?} packet rejected: {}", name, packet_type, error),
+        None => warn!("{} {:?} packet rejected: {}", name, state, error),
+    }
+}
@@ -5,41 +5,81 @@ use crate::{
     auth::LoginResult,
     chat::Text,
     hash_map,
-    util::{Handle, IOResult},
+    util::{Handle, IOError, IOErrorKind, IOResult},
 };
 use base64::Engine;
+use chat_pipeline::{ChatConfig, ChatPipeline, ChatPipelineBuilder};
 use command::{CommandRegistry, CommandRegistryBuilder};
+use anti_bot::AntiBotConfig;
+use circuit_breaker::CircuitBreakerConfig;
+use health::HealthCheckConfig;
+use limits::LimitsConfig;
+use network::NetworkConfig;
+use packet_limiter::PacketLimiterConfig;
+use status::StatusConfig;
+use throttle::ConnectionThrottleConfig;
 use image::{imageops::FilterType, ImageFormat};
 use log::{error, info, warn};
-use packets::{PlayerPublicKey, ProtocolState, SystemChatMessage};
+use boss_bar::BossBar;
+use packet_ids::PacketRegistry;
+use packets::{
+    BossBarAction, BossBarPacket, ClearTitles, EntitySoundEffect, Packet, PlayerPublicKey,
+    ProtocolState, ServerPacket, SetActionBarText, SetSubtitleText, SetTitleText, SetTitleTimes,
+    SoundCategory, SystemChatMessage, TabListHeaderFooter,
+};
 use proxy_handler::{ClientHandle, ConnectionHandle, PlayerSyncData};
+use resource_pack::ResourcePackConfig;
+use server_group::{ServerGroup, ServerGroupConfig};
+use server_links::ServerLinkConfig;
+use tab_list::TabListConfig;
+use limbo::LimboConfig;
+use queue::QueueConfig;
 use rsa::{RsaPrivateKey, RsaPublicKey};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
     collections::HashMap,
     future::Future,
     io::Cursor,
+    net::SocketAddr,
     path::{Path, PathBuf},
-    sync::atomic::Ordering,
-    time::{Duration, Instant},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 use tokio::io::AsyncWriteExt;
 use tokio::time::sleep;
 use tokio::{net::TcpListener, runtime::Runtime, sync::RwLock, task::JoinHandle};
 use uuid::Uuid;
 
+pub(crate) mod anti_bot;
 pub(crate) mod backend;
+pub(crate) mod boss_bar;
 pub(crate) mod brigadier;
+pub(crate) mod buffer_pool;
+pub(crate) mod chat_pipeline;
+pub(crate) mod circuit_breaker;
 pub(crate) mod command;
 pub(crate) mod compression;
 pub(crate) mod encryption;
+pub(crate) mod health;
 pub(crate) mod initial_handler;
+pub(crate) mod limbo;
+pub(crate) mod limits;
 pub(crate) mod nbt;
+pub(crate) mod network;
 pub(crate) mod packet_handler;
 pub(crate) mod packet_ids;
+pub(crate) mod packet_limiter;
 pub(crate) mod packets;
 pub(crate) mod proxy_handler;
+pub(crate) mod queue;
+pub(crate) mod rate_limit;
+pub(crate) mod resource_pack;
+pub(crate) mod server_group;
+pub(crate) mod server_links;
 pub(crate) mod status;
+pub(crate) mod tab_list;
+pub(crate) mod throttle;
 
 pub const NAME: &str = "Crust";
 pub const GIT_COMMIT_ID: &str = env!("GIT_COMMIT");
@@ -51,10 +91,9 @@ pub struct ProxyConfig {
     pub bind_address: String,
     pub worker_threads: usize,
     pub compression_threshold: i32,
-    pub motd: String,
+    /// A string with legacy colour codes or a json text component
+    pub motd: Value,
     pub favicon: Option<PathBuf>,
-    pub connection_throttle_time: i32,
-    pub connection_throttle_limit: u8,
     pub max_players: i32,
     pub online_mode: bool,
     pub offline_mode_encryption: bool,
@@ -67,12 +106,82 @@ pub struct ProxyConfig {
     pub proxy_protocol: bool,
     pub groups: HashMap<String, Vec<String>>,
     pub users: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub resource_packs: Vec<ResourcePackConfig>,
+    #[serde(default)]
+    pub forced_hosts: HashMap<String, ForcedHostConfig>,
+    #[serde(default)]
+    pub server_links: Vec<ServerLinkConfig>,
+    #[serde(default)]
+    pub tab_list: TabListConfig,
+    #[serde(default)]
+    pub limbo: LimboConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    /// Group names can be used like server labels
+    #[serde(default)]
+    pub server_groups: HashMap<String, ServerGroupConfig>,
+    #[serde(default)]
+    pub health_check: HealthCheckConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub connection_throttle: ConnectionThrottleConfig,
+    /// Deprecated, mapped onto ``connection_throttle.per_ip``
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_throttle_time: Option<i32>,
+    /// Deprecated, mapped onto ``connection_throttle.per_ip``
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection_throttle_limit: Option<u8>,
+    #[serde(default)]
+    pub anti_bot: AntiBotConfig,
+    #[serde(default)]
+    pub packet_limiter: PacketLimiterConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub chat: ChatConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub label: String,
     pub address: String,
+    #[serde(default)]
+    pub resource_packs: Vec<ResourcePackConfig>,
+    #[serde(default)]
+    pub server_links: Option<Vec<ServerLinkConfig>>,
+    /// Milliseconds until opening the connection to the server is given up
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+    /// Milliseconds the server may take from connecting until the login is finished
+    #[serde(default = "default_login_timeout")]
+    pub login_timeout: u64,
+    /// Sends a PROXY protocol v2 header with the address of the player, the server has to expect it on every connection
+    #[serde(default)]
+    pub proxy_protocol: bool,
+}
+
+fn default_connect_timeout() -> u64 {
+    5000
+}
+
+fn default_login_timeout() -> u64 {
+    10000
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForcedHostConfig {
+    #[serde(default)]
+    pub priorities: Vec<String>,
+    #[serde(default)]
+    pub resource_packs: Vec<ResourcePackConfig>,
+    #[serde(default)]
+    pub motds: Vec<Value>,
 }
 
 impl Default for ProxyConfig {
@@ -81,9 +190,7 @@ impl Default for ProxyConfig {
             bind_address: "0.0.0.0:25577".to_owned(),
             worker_threads: 0,
             compression_threshold: 256,
-            motd: "A Rust Minecraft Proxy".to_owned(),
-            connection_throttle_time: 5000,
-            connection_throttle_limit: 20,
+            motd: Value::String("A Rust Minecraft Proxy".to_owned()),
             favicon: None,
             max_players: 100,
             online_mode: false,
@@ -94,6 +201,11 @@ impl Default for ProxyConfig {
             servers: vec![ServerConfig {
                 label: "lobby".to_owned(),
                 address: "127.0.0.1:25565".to_owned(),
+                resource_packs: Vec::new(),
+                server_links: None,
+                connect_timeout: default_connect_timeout(),
+                login_timeout: default_login_timeout(),
+                proxy_protocol: false,
             }],
             priorities: vec!["lobby".to_owned()],
             max_packet_per_second: 2000,
@@ -103,6 +215,24 @@ impl Default for ProxyConfig {
                 "default".to_owned() => vec!["crust.command.server".to_owned()]
             },
             users: hash_map!("Outfluencer".to_owned() => vec!["admin".to_owned()]),
+            resource_packs: Vec::new(),
+            forced_hosts: HashMap::new(),
+            server_links: Vec::new(),
+            tab_list: TabListConfig::default(),
+            limbo: LimboConfig::default(),
+            queue: QueueConfig::default(),
+            server_groups: HashMap::new(),
+            health_check: HealthCheckConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            status: StatusConfig::default(),
+            connection_throttle: ConnectionThrottleConfig::default(),
+            connection_throttle_time: None,
+            connection_throttle_limit: None,
+            anti_bot: AntiBotConfig::default(),
+            packet_limiter: PacketLimiterConfig::default(),
+            limits: LimitsConfig::default(),
+            network: NetworkConfig::default(),
+            chat: ChatConfig::default(),
         }
     }
 }
@@ -111,11 +241,19 @@ impl Default for ProxyConfig {
 pub struct ServerInfo {
     pub label: String,
     pub address: String,
+    pub resource_packs: Vec<ResourcePackConfig>,
+    pub server_links: Option<Vec<ServerLinkConfig>>,
+    /// players connecting to an unavailable server are put into its queue
+    pub available: bool,
+    pub connect_timeout: u64,
+    pub login_timeout: u64,
+    pub proxy_protocol: bool,
 }
 
 pub struct ServerList {
     priorities: Vec<String>,
     servers_by_name: HashMap<String, ServerInfo>,
+    groups: HashMap<String, ServerGroup>,
 }
 
 impl ServerList {
@@ -142,15 +280,45 @@ impl ServerList {
         false
     }
 
+    pub fn set_available(&mut self, label: &str, available: bool) -> bool {
+        if let Some(server) = self.servers_by_name.get_mut(label) {
+            server.available = available;
+            return true;
+        }
+        false
+    }
+
     pub fn list_servers(&self) -> impl Iterator<Item = &ServerInfo> {
         self.servers_by_name.values()
     }
+
+    pub fn get_group(&self, name: &str) -> Option<&ServerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn all_groups(&self) -> impl Iterator<Item = &ServerGroup> {
+        self.groups.values()
+    }
+
+    pub fn add_group(&mut self, group: ServerGroup) {
+        self.groups.insert(group.name.clone(), group);
+    }
+
+    pub fn remove_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Whether a server or a group has this name
+    pub fn contains(&self, name: &str) -> bool {
+        self.servers_by_name.contains_key(name) || self.groups.contains_key(name)
+    }
 }
 
 pub struct ProxyServer {
     runtime: Runtime,
     config: ProxyConfig,
     command_registry: CommandRegistry,
+    chat_pipeline: ChatPipeline,
     servers: RwLock<ServerList>,
     rsa_priv_key: RsaPrivateKey,
     rsa_pub_key: RsaPublicKey,
@@ -171,6 +339,10 @@ impl ProxyServer {
         &self.command_registry
     }
 
+    pub fn chat_pipeline(&self) -> &ChatPipeline {
+        &self.chat_pipeline
+    }
+
     pub fn servers(&self) -> &RwLock<ServerList> {
         &self.servers
     }
@@ -233,7 +405,7 @@ impl ProxyServer {
 pub fn run_server() {
     info!("Starting {}..", FULL_NAME);
     let config_path = Path::new("config.json");
-    let config = if !config_path.exists() {
+    let mut config = if !config_path.exists() {
         let default_config = ProxyConfig::default();
         let default_config_json = serde_json::to_string_pretty(&default_config).unwrap();
         if let Err(e) = std::fs::write("config.json", default_config_json) {
@@ -255,6 +427,7 @@ pub fn run_server() {
             }
         }
     };
+    throttle::apply_legacy_config(&mut config);
 
     let icon_path = config.favicon.as_ref();
     let icon = if let Some(icon_path) = icon_path {
@@ -291,6 +464,44 @@ pub fn run_server() {
 
     info!("Loaded proxy config.");
 
+    if !init(config, icon) {
+        return;
+    }
+
+    #[cfg(feature = "plugin-system")]
+    ProxyServer::instance().block_on(async move {
+        if !PluginManager::load_plugins() {
+            log::error!("Error while loading plugins, shutting down.");
+            return;
+        }
+    });
+
+    tab_list::start();
+    queue::start();
+    health::start();
+    anti_bot::start();
+
+    ProxyServer::instance().spawn_task(async move {
+        let bind_address = &ProxyServer::instance().config.bind_address;
+        let address = tokio::net::lookup_host(bind_address)
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .unwrap_or_else(|| panic!("Invalid bind address {}", bind_address));
+        let listeners = network::bind_listeners(address).unwrap();
+
+        info!("Listening on {}", listeners[0].local_addr().unwrap());
+        let listeners: Vec<_> = listeners.into_iter().map(Arc::new).collect();
+        let accept_loops = ProxyServer::instance().config.network.accept_loops.max(1);
+        for i in 0..accept_loops {
+            let listener = listeners[i % listeners.len()].clone();
+            ProxyServer::instance().spawn_task(accept_loop(listener));
+        }
+    });
+}
+
+/// Creates the runtime and the proxy instance, nothing is started yet
+pub fn init(config: ProxyConfig, favicon: Option<String>) -> bool {
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .worker_threads(match config.worker_threads {
@@ -300,7 +511,7 @@ pub fn run_server() {
         .build();
     if let Err(e) = runtime {
         log::error!("Failed to create runtime: {}", e);
-        return;
+        return false;
     }
     let runtime = runtime.unwrap();
     info!(
@@ -311,7 +522,7 @@ pub fn run_server() {
     let priv_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024);
     if let Err(e) = priv_key {
         error!("Failed to generate RSA key pair: {}", e);
-        return;
+        return false;
     }
     let priv_key = priv_key.unwrap();
     let pub_key = RsaPublicKey::from(&priv_key);
@@ -319,82 +530,63 @@ pub fn run_server() {
     let mut server_list = ServerList {
         priorities: config.priorities.clone(),
         servers_by_name: HashMap::new(),
+        groups: HashMap::new(),
     };
     for entry in &config.servers {
         server_list.add_server(ServerInfo {
             label: entry.label.clone(),
             address: entry.address.clone(),
+            resource_packs: entry.resource_packs.clone(),
+            server_links: entry.server_links.clone(),
+            available: true,
+            connect_timeout: entry.connect_timeout,
+            login_timeout: entry.login_timeout,
+            proxy_protocol: entry.proxy_protocol,
         });
     }
+    for (name, group) in &config.server_groups {
+        if server_list.get_server_by_name(name).is_some() {
+            warn!("Server group {} has the name of a server and will never be used", name);
+        }
+        server_list.add_group(ServerGroup::new(name.clone(), group));
+    }
 
     let commands = command::core_impl::register_all(CommandRegistryBuilder::new());
+    let chat_pipeline = chat_pipeline::register_all(ChatPipelineBuilder::new(), &config.chat);
 
     unsafe {
         INSTANCE = Some(ProxyServer {
             runtime,
             command_registry: commands.build(),
+            chat_pipeline: chat_pipeline.build(),
             rsa_priv_key: priv_key,
             rsa_pub_key: pub_key,
             servers: RwLock::new(server_list),
             player_count: 0,
             config,
-            favicon: icon,
+            favicon,
             player_by_name: RwLock::new(HashMap::new()),
             player_by_uuid: RwLock::new(HashMap::new())
         });
     }
+    true
+}
 
-    #[cfg(feature = "plugin-system")]
-    ProxyServer::instance().block_on(async move {
-        if !PluginManager::load_plugins() {
-            log::error!("Error while loading plugins, shutting down.");
-            return;
-        }
-    });
-
-    ProxyServer::instance().spawn_task(async move {
-        let listener = TcpListener::bind(&ProxyServer::instance().config.bind_address)
-            .await
-            .unwrap();
-
-        info!("Listening on {}", listener.local_addr().unwrap());
-        let mut map = HashMap::new();
-        let mut time = Instant::now();
-        let connection_throttle = ProxyServer::instance().config.connection_throttle_time > 0;
-        let interval =
-            Duration::from_millis(ProxyServer::instance().config().connection_throttle_time as u64);
-        let limit = ProxyServer::instance().config().connection_throttle_limit;
-        loop {
-            match listener.accept().await {
-                Ok((stream, peer_addr)) => {
-                    if connection_throttle {
-                        let counter = map.entry(peer_addr.ip()).or_insert(0u8);
-                        *counter += 1;
-
-                        let now = Instant::now();
-                        let mut clear = false;
-                        if now.duration_since(time) >= interval {
-                            *counter = 0;
-                            time = now;
-                            clear = true;
-                        }
-
-                        if *counter > limit {
-                            continue;
-                        }
-                        if clear {
-                            map.clear();
-                        }
-                    }
-                    initial_handler::handle(stream, peer_addr).await;
-                }
-                Err(err) => {
-                    // probably out of file descriptors
-                    log::debug!("Failed to accept connection: {}", err);
+async fn accept_loop(listener: Arc<TcpListener>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer_addr)) => {
+                if let Err(err) = network::configure_stream(&stream) {
+                    log::debug!("Failed to configure connection of {}: {}", peer_addr, err);
                 }
+                initial_handler::handle(stream, peer_addr).await;
+            }
+            Err(err) => {
+                // probably out of file descriptors
+                log::debug!("Failed to accept connection: {}", err);
             }
         }
-    });
+    }
 }
 
 pub struct ProxiedPlayer {
@@ -406,6 +598,9 @@ pub struct ProxiedPlayer {
     pub client_handle: ConnectionHandle,
     pub server_handle: Option<ConnectionHandle>,
     pub protocol_version: i32,
+    pub virtual_host: String,
+    /// address of the proxy the client connected to
+    pub local_address: SocketAddr,
     pub(crate) sync_data: PlayerSyncData,
 }
 
@@ -442,18 +637,7 @@ impl ProxiedPlayer {
     }
 
     pub async fn send_message(&self, message: Text) -> IOResult<()> {
-        let chat = SystemChatMessage { message, pos: 0 };
-        let data = packets::get_full_server_packet_buf(
-            &chat,
-            self.protocol_version,
-            self.client_handle.protocol_state(),
-        )?;
-        if let Some(data) = data {
-            return self.client_handle.queue_packet(data, false).await;
-        } else {
-            println!("packet not in current state");
-        }
-        Ok(())
+        self.send_packet(&SystemChatMessage { message, pos: 0 }).await
     }
 
     pub async fn kick<T: Into<Text>>(&self, text: T) -> IOResult<()> {
@@ -471,6 +655,111 @@ impl ProxiedPlayer {
         Ok(())
     }
 
+    /// Fails with ``Unsupported`` if the version or current state of the client has no such packet
+    pub async fn send_packet<P: Packet + ServerPacket>(&self, packet: &P) -> IOResult<()> {
+        let state = self.client_handle.protocol_state();
+        if PacketRegistry::instance()
+            .get_server_packet_id(state, self.protocol_version, packet.get_type())
+            .is_none()
+        {
+            return Err(IOError::new(
+                IOErrorKind::Unsupported,
+                format!("{:?} can't be sent to the client in the {:?} state", packet.get_type(), state),
+            ));
+        }
+        if let Some(data) = packets::get_full_server_packet_buf(packet, self.protocol_version, state)? {
+            self.client_handle.queue_packet(data, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Times are in ticks, the client keeps them for the following titles
+    pub async fn send_title<T: Into<Text>>(
+        &self,
+        title: T,
+        subtitle: Option<Text>,
+        fade_in: i32,
+        stay: i32,
+        fade_out: i32,
+    ) -> IOResult<()> {
+        self.send_packet(&SetTitleTimes {
+            fade_in,
+            stay,
+            fade_out,
+        })
+        .await?;
+        if let Some(subtitle) = subtitle {
+            self.send_packet(&SetSubtitleText { text: subtitle }).await?;
+        }
+        self.send_packet(&SetTitleText { text: title.into() }).await
+    }
+
+    pub async fn clear_title(&self, reset: bool) -> IOResult<()> {
+        self.send_packet(&ClearTitles { reset }).await
+    }
+
+    pub async fn send_action_bar<T: Into<Text>>(&self, text: T) -> IOResult<()> {
+        self.send_packet(&SetActionBarText { text: text.into() }).await
+    }
+
+    pub async fn set_tab_list_header_footer(&self, header: Text, footer: Text) -> IOResult<()> {
+        self.send_packet(&TabListHeaderFooter { header, footer }).await
+    }
+
+    /// Plays the sound at the position of the player, `sound` is the name like ``minecraft:entity.player.levelup``
+    pub async fn play_sound(
+        &self,
+        sound: &str,
+        category: SoundCategory,
+        volume: f32,
+        pitch: f32,
+    ) -> IOResult<()> {
+        self.send_packet(&EntitySoundEffect {
+            sound: sound.to_owned(),
+            fixed_range: None,
+            category,
+            entity_id: self.sync_data.entity_id.load(Ordering::Relaxed),
+            volume,
+            pitch,
+            seed: rand::random(),
+        })
+        .await
+    }
+
+    /// Shows the boss bar or updates it if it is already shown, it is kept even if the client
+    /// can't show it right now and sent again on the next server
+    pub async fn show_boss_bar(&self, bar: BossBar) -> IOResult<()> {
+        let mut boss_bars = self.sync_data.boss_bars.lock().await;
+        let packets = match boss_bars.get(&bar.uuid) {
+            Some(old) => bar.update_packets(old),
+            None => vec![bar.add_packet()],
+        };
+        boss_bars.insert(bar.uuid, bar);
+        for packet in packets {
+            self.send_packet(&packet).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn hide_boss_bar(&self, uuid: &Uuid) -> IOResult<()> {
+        if self.sync_data.boss_bars.lock().await.remove(uuid).is_some() {
+            self.send_packet(&BossBarPacket {
+                uuid: *uuid,
+                action: BossBarAction::Remove,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// The client forgets boss bars when it joins another server
+    pub(crate) async fn resend_boss_bars(&self) -> IOResult<()> {
+        for bar in self.sync_data.boss_bars.lock().await.values() {
+            self.send_packet(&bar.add_packet()).await?;
+        }
+        Ok(())
+    }
+
     pub async fn switch_server(
         mut player: Handle<ProxiedPlayer>,
         server: String,
@@ -493,9 +782,8 @@ impl ProxiedPlayer {
                 return false;
             }
 
-            let (addr, server_name) = {
-                let server_list = ProxyServer::instance().servers().read().await;
-                let server = server_list.get_server_by_name(&server);
+            let (server_info, server_name, available) = {
+                let server = server_group::resolve(&server).await;
                 if server.is_none() {
                     *player
                         .sync_data
@@ -503,13 +791,23 @@ impl ProxiedPlayer {
                     return false;
                 }
                 let server = server.unwrap();
-                (server.address.clone(), server.label.clone())
+                let label = server.label.clone();
+                let available = server.available;
+                (server, label, available)
             };
+            if (!available || !health::is_healthy(&server_name)) && queue::is_enabled() {
+                *player
+                    .sync_data
+                    .is_switching_server.lock().await = false;
+                queue::enqueue(&player, &server_name).await;
+                return false;
+            }
 
             let username = player.name.clone();
             let backend = backend::connect(
                 player.client_handle.address,
-                addr,
+                player.local_address,
+                &server_info,
                 "127.0.0.1".to_string(),
                 25565,
                 player.login_result.clone(),
@@ -522,8 +820,12 @@ impl ProxiedPlayer {
                 *player
                     .sync_data
                     .is_switching_server.lock().await = false;
+                if queue::should_queue(&e) {
+                    queue::enqueue(&player, &server_name).await;
+                    return false;
+                }
                 let _ = player
-                    .send_message(Text::new(format!("§cCould not connect: {}", e)))
+                    .send_message(Text::new(format!("§cCould not connect: {}", e.user_message())))
                     .await;
                 return false;
             }
@@ -538,21 +840,28 @@ impl ProxiedPlayer {
                         .await;
                     server_handle.wait_for_disconnect().await;
                 }
-                if player.client_handle.protocol_state() == ProtocolState::Config {
-                    player.client_handle.goto_game(version).await.ok();
-                    player.sync_data.game_ack_notify.notified().await;
-                    // client needs some time to change states somehow if it was in config, otherwise protocol error occours in client
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+                let state = player.client_handle.protocol_state();
+                // a client waiting in the limbo without a world can be configured by the new server right away
+                if !(state == ProtocolState::Config && player.sync_data.in_limbo.load(Ordering::Relaxed)) {
+                    if state == ProtocolState::Config {
+                        player.client_handle.goto_game(version).await.ok();
+                        player.sync_data.game_ack_notify.notified().await;
+                        // client needs some time to change states somehow if it was in config, otherwise protocol error occours in client
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+
+                    player.client_handle.goto_config(version).await.ok();
+                    player.sync_data.config_ack_notify.notified().await;
                 }
-                
-                player.client_handle.goto_config(version).await.ok();
-                player.sync_data.config_ack_notify.notified().await;
+                // the client forgets its tab list when entering the config state
+                player.sync_data.tab_list.lock().await.clear();
                 player.client_handle.drop_redundant(false).await.ok();
             }
 
             if let Some(read_task) = player.client_handle.read_task.lock().await.take() {
                 read_task.abort();
             }
+            *player.sync_data.limbo_capture.lock().await = Default::default();
 
             let (login_result, server_handle) = backend
                 .begin_proxying(
@@ -586,12 +895,19 @@ impl ProxiedPlayer {
             }
             drop(settings);
 
+            if let Err(e) = resource_pack::send_server_packs(&player, &server_name).await {
+                warn!("[{}] Failed to send resource packs: {}", username, e);
+            }
+            if let Err(e) = server_links::send_server_links(&player, &server_name).await {
+                warn!("[{}] Failed to send server links: {}", username, e);
+            }
+
             let display_name = format!("[{} - {}]", username, server_name);
 
             player
                 .client_handle
                 .spawn_read_task(
-                    false,
+                    true,
                     display_name,
                     server_handle.clone(),
                     player.downgrade(),
@@ -599,12 +915,14 @@ impl ProxiedPlayer {
                 )
                 .await;
 
-            player.current_server = Some(server.to_string());
+            player.current_server = Some(server_name);
             player.server_handle = Some(server_handle);
             player.login_result = login_result;
+            player.sync_data.in_limbo.store(false, Ordering::Relaxed);
             *player
                 .sync_data
                 .is_switching_server.lock().await = false;
+            tab_list::request_update();
             true
         });
         Some(join_handle)
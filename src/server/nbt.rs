@@ -9,6 +9,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Map, Number, Value};
 use std::{collections::HashMap, fmt::Debug, io::ErrorKind};
 
+use super::limits::{self, limit_error};
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum NbtType {
     ByteTag(i8),
@@ -453,9 +455,11 @@ pub fn read_networking_nbt<R: ReadBytesExt + ?Sized>(
     input: &mut R,
     version: i32,
 ) -> IOResult<Either<Option<NbtType>, NamedTag>> {
+    let limits = limits::limits();
     let mut counter = NbtCounter {
         depth: 0,
-        max_bytes: u64::MAX,
+        max_depth: limits.max_nbt_depth,
+        max_bytes: limits.max_nbt_bytes,
         used_bytes: 0,
     };
     let tag_type = input.read_i8()?;
@@ -523,12 +527,16 @@ pub struct NbtCounter {
     used_bytes: u64,
     max_bytes: u64,
     depth: u16,
+    max_depth: u16,
 }
 
 impl NbtCounter {
     pub fn account_bytes(&mut self, bytes: u64) -> IOResult<()> {
         if self.used_bytes + bytes > self.max_bytes {
-            return Err(IOError::new(ErrorKind::InvalidData, "exeeded byte limit"));
+            return Err(limit_error(format!(
+                "NBT exceeded the byte limit of {}",
+                self.max_bytes
+            )));
         }
         self.used_bytes += bytes;
         Ok(())
@@ -536,8 +544,11 @@ impl NbtCounter {
 
     pub fn push(&mut self) -> IOResult<()> {
         self.depth += 1;
-        if self.depth > 512 {
-            return Err(IOError::new(ErrorKind::InvalidData, "depth limit exeeded"));
+        if self.depth > self.max_depth {
+            return Err(limit_error(format!(
+                "NBT exceeded the depth limit of {}",
+                self.max_depth
+            )));
         }
         Ok(())
     }
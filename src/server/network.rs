@@ -0,0 +1,548 @@
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::{tcp, TcpListener, TcpSocket, TcpStream},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Notify,
+    },
+    time::Instant,
+};
+
+use crate::util::{IOError, IOErrorKind, IOResult, VarInt};
+
+use super::{compression, encryption::PacketEncryption, ProxyServer};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Queued packets are written together until a batch has this many bytes
+    #[serde(default = "default_write_batch_size")]
+    pub write_batch_size: usize,
+    /// Milliseconds a batch waits for more packets before it is written, 0 writes as soon as the queue is empty
+    #[serde(default)]
+    pub flush_interval: u64,
+    /// Bytes of packets that may wait to be written to a connection, 0 for no limit
+    #[serde(default = "default_max_queued_bytes")]
+    pub max_queued_bytes: usize,
+    #[serde(default = "default_queue_full_action")]
+    pub queue_full_action: QueueFullAction,
+    /// Milliseconds queueing a packet may wait for room before the connection is closed as too slow
+    #[serde(default = "default_send_timeout")]
+    pub send_timeout: u64,
+    /// Disables Nagle's algorithm on client and backend connections
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// TCP keepalive of client and backend connections, none to disable it
+    #[serde(default)]
+    pub keepalive: Option<KeepaliveConfig>,
+    /// Socket buffer sizes in bytes, 0 keeps the system default
+    #[serde(default)]
+    pub send_buffer_size: usize,
+    #[serde(default)]
+    pub recv_buffer_size: usize,
+    /// Binds ``accept_loops`` listeners to the same address so the kernel spreads new connections
+    #[serde(default)]
+    pub reuse_port: bool,
+    #[serde(default = "default_accept_loops")]
+    pub accept_loops: usize,
+    /// Whether a listener on an IPv6 address accepts only IPv6 connections, none keeps the system default
+    #[serde(default)]
+    pub ipv6_only: Option<bool>,
+    #[serde(default = "default_backlog")]
+    pub backlog: u32,
+    /// Local address connections to backend servers are bound to, like ``10.0.0.2`` or ``10.0.0.2:0``
+    #[serde(default)]
+    pub backend_bind_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepaliveConfig {
+    /// Milliseconds a connection is idle before the first probe is sent
+    #[serde(default = "default_keepalive_time")]
+    pub time: u64,
+    /// Milliseconds between probes
+    #[serde(default = "default_keepalive_interval")]
+    pub interval: u64,
+    /// Unanswered probes until the connection is closed
+    #[serde(default = "default_keepalive_retries")]
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueFullAction {
+    /// the partner stops reading until there is room again or ``send_timeout`` passed
+    Wait,
+    Disconnect,
+}
+
+fn default_write_batch_size() -> usize {
+    64 * 1024
+}
+
+fn default_max_queued_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_queue_full_action() -> QueueFullAction {
+    QueueFullAction::Wait
+}
+
+fn default_send_timeout() -> u64 {
+    10000
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_accept_loops() -> usize {
+    1
+}
+
+fn default_backlog() -> u32 {
+    1024
+}
+
+fn default_keepalive_time() -> u64 {
+    60000
+}
+
+fn default_keepalive_interval() -> u64 {
+    10000
+}
+
+fn default_keepalive_retries() -> u32 {
+    3
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            write_batch_size: default_write_batch_size(),
+            flush_interval: 0,
+            max_queued_bytes: default_max_queued_bytes(),
+            queue_full_action: default_queue_full_action(),
+            send_timeout: default_send_timeout(),
+            tcp_nodelay: default_tcp_nodelay(),
+            keepalive: None,
+            send_buffer_size: 0,
+            recv_buffer_size: 0,
+            reuse_port: false,
+            accept_loops: default_accept_loops(),
+            ipv6_only: None,
+            backlog: default_backlog(),
+            backend_bind_address: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn send_timeout(&self) -> Duration {
+        Duration::from_millis(self.send_timeout)
+    }
+}
+
+pub const TOO_SLOW: &str = "connection too slow";
+
+fn network() -> &'static NetworkConfig {
+    &ProxyServer::instance().config().network
+}
+
+/// Sets the buffer sizes, they have to be set before connecting for the TCP window to use them
+fn set_buffer_sizes(socket: &Socket) -> IOResult<()> {
+    let config = network();
+    if config.send_buffer_size > 0 {
+        socket.set_send_buffer_size(config.send_buffer_size)?;
+    }
+    if config.recv_buffer_size > 0 {
+        socket.set_recv_buffer_size(config.recv_buffer_size)?;
+    }
+    Ok(())
+}
+
+/// Applies the options of connected sockets
+pub fn configure_stream(stream: &TcpStream) -> IOResult<()> {
+    let config = network();
+    let socket = SockRef::from(stream);
+    socket.set_tcp_nodelay(config.tcp_nodelay)?;
+    if let Some(keepalive) = &config.keepalive {
+        socket.set_tcp_keepalive(
+            &TcpKeepalive::new()
+                .with_time(Duration::from_millis(keepalive.time))
+                .with_interval(Duration::from_millis(keepalive.interval))
+                .with_retries(keepalive.retries),
+        )?;
+    }
+    Ok(())
+}
+
+/// Binds the listeners of the proxy, one per accept loop with ``reuse_port`` and a single one otherwise
+pub fn bind_listeners(address: SocketAddr) -> IOResult<Vec<TcpListener>> {
+    let config = network();
+    let count = if config.reuse_port {
+        config.accept_loops.max(1)
+    } else {
+        1
+    };
+    (0..count).map(|_| bind_listener(address)).collect()
+}
+
+fn bind_listener(address: SocketAddr) -> IOResult<TcpListener> {
+    let config = network();
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    #[cfg(unix)]
+    {
+        socket.set_reuse_address(true)?;
+        if config.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+    }
+    #[cfg(not(unix))]
+    if config.reuse_port {
+        log::warn!("reuse_port is not supported on this platform");
+    }
+    if let (SocketAddr::V6(_), Some(ipv6_only)) = (address, config.ipv6_only) {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    set_buffer_sizes(&socket)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(config.backlog.min(i32::MAX as u32) as i32)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Prefix of backend addresses that are Unix domain sockets, like ``unix:/run/paper.sock``
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// Opens a connection to a backend server
+pub async fn connect(address: &str) -> IOResult<Stream> {
+    match address.strip_prefix(UNIX_PREFIX) {
+        #[cfg(unix)]
+        Some(path) => Ok(Stream::Unix(tokio::net::UnixStream::connect(path).await?)),
+        #[cfg(not(unix))]
+        Some(_) => Err(IOError::new(
+            IOErrorKind::Unsupported,
+            "Unix sockets are not supported on this platform",
+        )),
+        None => Ok(Stream::Tcp(connect_tcp(address).await?)),
+    }
+}
+
+/// Every resolved address is tried until one accepts the connection
+async fn connect_tcp(address: &str) -> IOResult<TcpStream> {
+    let config = network();
+    let bind_address = match &config.backend_bind_address {
+        Some(bind_address) => Some(parse_bind_address(bind_address)?),
+        None => None,
+    };
+    let mut last_error = None;
+    for address in tokio::net::lookup_host(address).await? {
+        let bind_address = match bind_address {
+            Some(bind_address) if bind_address.is_ipv4() != address.is_ipv4() => continue,
+            bind_address => bind_address,
+        };
+        match connect_to(address, bind_address).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        IOError::new(
+            IOErrorKind::AddrNotAvailable,
+            format!("No address of {} can be connected to", address),
+        )
+    }))
+}
+
+async fn connect_to(address: SocketAddr, bind_address: Option<SocketAddr>) -> IOResult<TcpStream> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    set_buffer_sizes(&socket)?;
+    if let Some(bind_address) = bind_address {
+        socket.bind(&bind_address.into())?;
+    }
+    socket.set_nonblocking(true)?;
+    let stream = TcpSocket::from_std_stream(socket.into())
+        .connect(address)
+        .await?;
+    configure_stream(&stream)?;
+    Ok(stream)
+}
+
+/// Parses an address with or without a port, port 0 picks any free port
+fn parse_bind_address(address: &str) -> IOResult<SocketAddr> {
+    address
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            address
+                .parse::<std::net::IpAddr>()
+                .map(|ip| SocketAddr::new(ip, 0))
+        })
+        .map_err(|_| {
+            IOError::new(
+                IOErrorKind::InvalidInput,
+                format!("Invalid backend bind address {}", address),
+            )
+        })
+}
+
+/// Bytes of the packets queued for a connection that the write task did not take yet
+#[derive(Default)]
+pub struct SendQueue {
+    bytes: AtomicUsize,
+    taken: Notify,
+}
+
+impl SendQueue {
+    /// Waits until ``size`` more bytes fit below the high-water mark, false if the connection is too slow.
+    /// A packet always fits into an empty queue.
+    pub async fn reserve<T>(&self, size: usize, deadline: Instant, sender: &Sender<T>) -> bool {
+        let config = &ProxyServer::instance().config().network;
+        loop {
+            let taken = self.taken.notified();
+            tokio::pin!(taken);
+            taken.as_mut().enable();
+
+            let queued = self.bytes.load(Ordering::Relaxed);
+            if config.max_queued_bytes == 0
+                || queued == 0
+                || queued + size <= config.max_queued_bytes
+                || sender.is_closed()
+            {
+                self.bytes.fetch_add(size, Ordering::Relaxed);
+                return true;
+            }
+            if config.queue_full_action == QueueFullAction::Disconnect
+                || tokio::time::timeout_at(deadline, taken).await.is_err()
+            {
+                return false;
+            }
+        }
+    }
+
+    /// Called by the write task for every packet it takes from the queue
+    pub fn release(&self, size: usize) {
+        self.bytes.fetch_sub(size, Ordering::Relaxed);
+        self.taken.notify_waiters();
+    }
+
+    /// Wakes everyone waiting for room, used once the connection is closed
+    pub fn wake(&self) {
+        self.taken.notify_waiters();
+    }
+}
+
+/// Compresses and encrypts the packets of a connection into one buffer that is written at once
+pub struct BatchWriter<W> {
+    write: W,
+    buf: Vec<u8>,
+    temp_buf: Vec<u8>,
+    compression: i32,
+    encryption: Option<PacketEncryption>,
+    max_batch_size: usize,
+    flush_interval: Option<Duration>,
+}
+
+impl<W: AsyncWrite + Unpin> BatchWriter<W> {
+    pub fn new(write: W, compression: i32, encryption: Option<PacketEncryption>) -> Self {
+        let config = &ProxyServer::instance().config().network;
+        Self {
+            write,
+            buf: Vec::new(),
+            temp_buf: Vec::new(),
+            compression,
+            encryption,
+            max_batch_size: config.write_batch_size,
+            flush_interval: (config.flush_interval > 0)
+                .then(|| Duration::from_millis(config.flush_interval)),
+        }
+    }
+
+    pub fn push_packet(&mut self, packet: &[u8]) -> IOResult<()> {
+        if self.compression < 0 {
+            return append_frame(&mut self.buf, packet, &mut self.encryption);
+        }
+        self.temp_buf.clear();
+        compression::compress(packet, self.compression, &mut self.temp_buf)?;
+        append_frame(&mut self.buf, &self.temp_buf, &mut self.encryption)
+    }
+
+    /// Adds a frame that is already compressed with the threshold of the connection
+    pub fn push_frame(&mut self, frame: &[u8]) -> IOResult<()> {
+        append_frame(&mut self.buf, frame, &mut self.encryption)
+    }
+
+    pub async fn flush(&mut self) -> IOResult<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.write.write_all(&self.buf).await?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// When a batch that starts now has to be written, none if it is written once the queue is empty
+    pub fn deadline(&self) -> Option<Instant> {
+        self.flush_interval
+            .map(|interval| Instant::now() + interval)
+    }
+
+    /// The next queued event that belongs to the current batch, none once the batch should be written
+    pub async fn next_event<T>(
+        &self,
+        receiver: &mut Receiver<T>,
+        deadline: Option<Instant>,
+    ) -> Option<T> {
+        if self.buf.len() >= self.max_batch_size {
+            return None;
+        }
+        if let Ok(event) = receiver.try_recv() {
+            return Some(event);
+        }
+        match deadline {
+            Some(deadline) if !self.buf.is_empty() => {
+                tokio::time::timeout_at(deadline, receiver.recv())
+                    .await
+                    .ok()
+                    .flatten()
+            }
+            _ => None,
+        }
+    }
+}
+
+fn append_frame(
+    buf: &mut Vec<u8>,
+    frame: &[u8],
+    encryption: &mut Option<PacketEncryption>,
+) -> IOResult<()> {
+    let start = buf.len();
+    if let Err(e) = VarInt(frame.len() as i32).encode(buf, 3) {
+        buf.truncate(start);
+        return Err(e);
+    }
+    buf.extend_from_slice(frame);
+    if let Some(encryption) = encryption {
+        encryption.encrypt(&mut buf[start..]);
+    }
+    Ok(())
+}
+
+macro_rules! dispatch {
+    ($value:expr, $inner:ident => $body:expr) => {
+        match $value {
+            Self::Tcp($inner) => $body,
+            #[cfg(unix)]
+            Self::Unix($inner) => $body,
+        }
+    };
+}
+
+/// A TCP connection or a connection to a backend server over a Unix socket
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+pub enum ReadHalf {
+    Tcp(tcp::OwnedReadHalf),
+    #[cfg(unix)]
+    Unix(tokio::net::unix::OwnedReadHalf),
+}
+
+pub enum WriteHalf {
+    Tcp(tcp::OwnedWriteHalf),
+    #[cfg(unix)]
+    Unix(tokio::net::unix::OwnedWriteHalf),
+}
+
+impl Stream {
+    pub fn into_split(self) -> (ReadHalf, WriteHalf) {
+        match self {
+            Self::Tcp(stream) => {
+                let (read, write) = stream.into_split();
+                (ReadHalf::Tcp(read), WriteHalf::Tcp(write))
+            }
+            #[cfg(unix)]
+            Self::Unix(stream) => {
+                let (read, write) = stream.into_split();
+                (ReadHalf::Unix(read), WriteHalf::Unix(write))
+            }
+        }
+    }
+
+    /// Unix sockets have no address, they are reported as the local host
+    pub fn peer_addr(&self) -> IOResult<SocketAddr> {
+        match self {
+            Self::Tcp(stream) => stream.peer_addr(),
+            #[cfg(unix)]
+            Self::Unix(_) => Ok(SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0))),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), stream => Pin::new(stream).poll_read(cx, buf))
+    }
+}
+
+impl AsyncRead for ReadHalf {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), read => Pin::new(read).poll_read(cx, buf))
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        dispatch!(self.get_mut(), stream => Pin::new(stream).poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), stream => Pin::new(stream).poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), stream => Pin::new(stream).poll_shutdown(cx))
+    }
+}
+
+impl AsyncWrite for WriteHalf {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        dispatch!(self.get_mut(), write => Pin::new(write).poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), write => Pin::new(write).poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        dispatch!(self.get_mut(), write => Pin::new(write).poll_shutdown(cx))
+    }
+}
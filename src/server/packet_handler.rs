@@ -14,17 +14,25 @@ use crate::{
     },
     util::{IOResult, WeakHandle},
 };
-use log::{error, info};
-use std::{future::Future, io::Cursor, pin::Pin, sync::atomic::Ordering};
+use log::{debug, error, info};
+use std::{future::Future, io::Cursor, pin::Pin, sync::atomic::Ordering, time::Instant};
 
 use super::{
     brigadier::{
         ArgumentProperty, CommandNode, CommandNodeType, Commands, StringParserType, SuggestionsType,
     },
-    command, nbt,
+    chat_pipeline::{self, ChatDestination, ChatEvent, ServerChatEvent},
+    command, limbo, limits, nbt,
     packet_ids::{ClientPacketType, PacketRegistry, ServerPacketType},
-    packets::{ClientSettings, Kick, ProtocolState, SystemChatMessage, UnsignedClientCommand},
+    packets::{
+        AddResourcePack, ClientChatMessage, ClientKeepAlive, ClientSettings,
+        CustomReportDetails, DisguisedChatMessage, JoinGame, Kick, PlayerChatMessage, PlayerInfoRemove,
+        PlayerInfoUpdate, ProtocolState, RemoveResourcePack, ResourcePackResponse,
+        ServerKeepAlive, ServerLinks, SignedClientCommand, SystemChatMessage,
+        UnsignedClientCommand,
+    },
     proxy_handler::ConnectionHandle,
+    resource_pack, server_links,
     ProxiedPlayer,
 };
 
@@ -70,6 +78,15 @@ impl ClientPacketHandler {
                 }
                 ClientPacketType::ClientCustomPayload => {
                     let packet = ClientCustomPayload::decode(&mut Cursor::new(buffer), version)?;
+                    let max_size = limits::limits().max_custom_payload_size(&packet.channel);
+                    if packet.data.len() > max_size {
+                        return Err(limits::limit_error(format!(
+                            "Payload of {} bytes on {} exceeds the limit of {} bytes",
+                            packet.data.len(),
+                            packet.channel,
+                            max_size
+                        )));
+                    }
                     if (version < R1_13 && packet.channel == "MC|Brand")
                         || (version >= R1_13 && packet.channel == "minecraft:brand")
                     {
@@ -80,34 +97,86 @@ impl ClientPacketHandler {
                 }
                 ClientPacketType::UnsignedClientCommand => {
                     let packet = UnsignedClientCommand::decode(&mut Cursor::new(buffer), version)?;
-                    let line = packet.message;
-                    let command_name = line
-                        .split_ascii_whitespace()
-                        .next()
-                        .unwrap_or("")
-                        .to_string();
-                    if ProxyServer::instance()
-                        .command_registry()
-                        .get_command_by_name(&command_name)
-                        .is_none()
+                    return Ok(!execute_proxy_command(player, packet.message));
+                }
+                ClientPacketType::SignedClientCommand => {
+                    let packet = SignedClientCommand::decode(&mut Cursor::new(buffer), version)?;
+                    if !execute_proxy_command(player, packet.command) {
+                        return Ok(true);
+                    }
+                    if let Some(player) = player.upgrade() {
+                        chat_pipeline::acknowledge_messages(&player, packet.offset).await?;
+                    }
+                    return Ok(false);
+                }
+                ClientPacketType::KeepAlive => {
+                    let packet = ClientKeepAlive::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        let mut pending = player.sync_data.pending_keep_alive.lock().await;
+                        if let Some((id, sent)) = *pending {
+                            if id == packet.id {
+                                player
+                                    .sync_data
+                                    .ping
+                                    .store(sent.elapsed().as_millis() as i32, Ordering::Relaxed);
+                                *pending = None;
+                            }
+                        }
+                    }
+                }
+                ClientPacketType::ClientChatMessage => {
+                    if !ProxyServer::instance().chat_pipeline().has_chat_handlers() {
+                        return Ok(true);
+                    }
+                    let mut packet = ClientChatMessage::decode(&mut Cursor::new(buffer), version)?;
+                    let event = chat_pipeline::call_chat(ChatEvent {
+                        player: player.clone(),
+                        message: packet.message.clone(),
+                        cancelled: false,
+                        destination: ChatDestination::Server,
+                        format: chat_pipeline::DEFAULT_CHAT_FORMAT.to_owned(),
+                    })
+                    .await?;
+                    if !event.cancelled
+                        && event.destination == ChatDestination::Server
+                        && event.message == packet.message
                     {
                         return Ok(true);
                     }
-                    let player_ = player.clone();
-                    tokio::task::spawn_blocking(move || {
-                        // Needs to be blocking because commands are executed synchronously
-                        if ProxyServer::instance()
-                            .command_registry()
-                            .execute(&CommandSender::Player(player_), &line)
-                        {
-                            return true;
-                        } else {
-                            log::debug!("Command not found '{}' passing command to server", line);
+                    let player = match player.upgrade() {
+                        Some(player) => player,
+                        None => return Ok(false),
+                    };
+                    if !event.cancelled
+                        && event.destination == ChatDestination::Server
+                        && packet.signature.is_none()
+                    {
+                        // unsigned messages can be changed without the backend noticing
+                        packet.message = event.message;
+                        if let Some(ref server_handle) = player.server_handle {
+                            if let Some(data) = packets::get_full_client_packet_buf(
+                                &packet,
+                                version,
+                                server_handle.protocol_state(),
+                            )? {
+                                server_handle.queue_packet(data, false).await?;
+                            }
                         }
-                        false
-                    });
+                        return Ok(false);
+                    }
+                    // a changed signed message would fail validation, the proxy sends it as system message instead
+                    chat_pipeline::acknowledge_messages(&player, packet.offset).await?;
+                    if !event.cancelled {
+                        chat_pipeline::deliver(&player, &event).await;
+                    }
                     return Ok(false);
                 }
+                ClientPacketType::ResourcePackResponse => {
+                    let packet = ResourcePackResponse::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        return Ok(resource_pack::handle_response(&player, &packet).await);
+                    }
+                }
                 ClientPacketType::TabCompleteRequest => {
                     let packet = TabCompleteRequest::decode(&mut Cursor::new(buffer), version)?;
                     let cursor = packet.cursor;
@@ -146,13 +215,52 @@ impl ClientPacketHandler {
     }
 }
 
+/// Runs the command on the proxy if it is registered there, returns false if it belongs to the backend
+fn execute_proxy_command(player: &WeakHandle<ProxiedPlayer>, line: String) -> bool {
+    let command_name = line
+        .split_ascii_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_string();
+    if ProxyServer::instance()
+        .command_registry()
+        .get_command_by_name(&command_name)
+        .is_none()
+    {
+        return false;
+    }
+    let player_ = player.clone();
+    tokio::task::spawn_blocking(move || {
+        // Needs to be blocking because commands are executed synchronously
+        if ProxyServer::instance()
+            .command_registry()
+            .execute(&CommandSender::Player(player_), &line)
+        {
+            return true;
+        } else {
+            log::debug!("Command not found '{}' passing command to server", line);
+        }
+        false
+    });
+    true
+}
+
 pub fn switch_server_helper(
     player: WeakHandle<ProxiedPlayer>,
     server_name: String,
 ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
     let block = async move {
         if let Some(player) = player.upgrade() {
-            if player.current_server.is_some() && player.current_server.as_ref().unwrap() == &*server_name {
+            let in_group = match player.current_server {
+                Some(ref current) => ProxyServer::instance()
+                    .servers()
+                    .read()
+                    .await
+                    .get_group(&server_name)
+                    .is_some_and(|group| group.servers.contains(current)),
+                None => false,
+            };
+            if in_group || player.current_server.as_deref() == Some(server_name.as_str()) {
                 player
                     .send_message(Text::new("§cYou're already connected to this server"))
                     .await
@@ -209,6 +317,215 @@ impl ServerPacketHandler {
 
                     return Ok(false);
                 }
+                ServerPacketType::AddResourcePack => {
+                    let packet = AddResourcePack::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        if resource_pack::handle_server_pack(&player, &packet, server_handle).await? {
+                            return Ok(false);
+                        }
+                    }
+                }
+                ServerPacketType::RemoveResourcePack => {
+                    let packet = RemoveResourcePack::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        resource_pack::handle_server_remove(&player, &packet).await;
+                    }
+                }
+                ServerPacketType::ServerLinks => {
+                    let mut packet = ServerLinks::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(ref server) = server_handle.server_name {
+                        server_links::merge_links(&mut packet, server).await;
+                    }
+                    if let Some(data) = packets::get_full_server_packet_buf(
+                        &packet,
+                        version,
+                        server_handle.protocol_state(),
+                    )? {
+                        client_handle.queue_packet(data, false).await?;
+                    }
+                    return Ok(false);
+                }
+                ServerPacketType::CustomReportDetails => {
+                    let mut packet = CustomReportDetails::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(ref server) = server_handle.server_name {
+                        server_links::merge_report_details(&mut packet, server);
+                    }
+                    if let Some(data) = packets::get_full_server_packet_buf(
+                        &packet,
+                        version,
+                        server_handle.protocol_state(),
+                    )? {
+                        client_handle.queue_packet(data, false).await?;
+                    }
+                    return Ok(false);
+                }
+                ServerPacketType::JoinGame => {
+                    let packet = JoinGame::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        player
+                            .sync_data
+                            .entity_id
+                            .store(packet.entity_id, Ordering::Relaxed);
+                        if let Some(data) = packets::get_full_server_packet_buf(
+                            &packet,
+                            version,
+                            server_handle.protocol_state(),
+                        )? {
+                            client_handle.queue_packet(data, false).await?;
+                        }
+                        player.resend_boss_bars().await?;
+                        return Ok(false);
+                    }
+                }
+                ServerPacketType::RegistryData
+                | ServerPacketType::FeatureFlags
+                | ServerPacketType::UpdateTags
+                | ServerPacketType::KnownPacks => {
+                    if let Some(player) = player.upgrade() {
+                        limbo::capture(&player, packet_type, packet_id, buffer).await?;
+                    }
+                }
+                ServerPacketType::ClientboundFinishConfigurationPacket => {
+                    if let Some(player) = player.upgrade() {
+                        limbo::finish_capture(&player).await;
+                    }
+                }
+                ServerPacketType::KeepAlive => {
+                    let packet = ServerKeepAlive::decode(&mut Cursor::new(buffer), version)?;
+                    if let Some(player) = player.upgrade() {
+                        *player.sync_data.pending_keep_alive.lock().await =
+                            Some((packet.id, Instant::now()));
+                    }
+                }
+                ServerPacketType::PlayerInfoUpdate => {
+                    // the entries of the backend are only tracked for the global tab list
+                    if !ProxyServer::instance().config().tab_list.global {
+                        return Ok(true);
+                    }
+                    match PlayerInfoUpdate::decode(&mut Cursor::new(buffer), version) {
+                        Ok(packet) => {
+                            if let Some(player) = player.upgrade() {
+                                player.sync_data.tab_list.lock().await.on_update(&packet);
+                            }
+                        }
+                        Err(e) => debug!("Could not decode player info update: {}", e),
+                    }
+                }
+                ServerPacketType::PlayerInfoRemove => {
+                    if !ProxyServer::instance().config().tab_list.global {
+                        return Ok(true);
+                    }
+                    match PlayerInfoRemove::decode(&mut Cursor::new(buffer), version) {
+                        Ok(packet) => {
+                            if let Some(player) = player.upgrade() {
+                                player.sync_data.tab_list.lock().await.on_remove(&packet);
+                            }
+                        }
+                        Err(e) => debug!("Could not decode player info remove: {}", e),
+                    }
+                }
+                // the proxy header and footer replace the ones of the backend
+                ServerPacketType::TabListHeaderFooter
+                    if ProxyServer::instance().config().tab_list.has_header_footer() =>
+                {
+                    return Ok(false);
+                }
+                ServerPacketType::SystemChatMessage => {
+                    if !ProxyServer::instance().chat_pipeline().has_server_chat_handlers() {
+                        return Ok(true);
+                    }
+                    let mut packet = SystemChatMessage::decode(&mut Cursor::new(buffer), version)?;
+                    if packet.pos == 2 {
+                        // action bar
+                        return Ok(true);
+                    }
+                    let event = chat_pipeline::call_server_chat(ServerChatEvent {
+                        receiver: player.clone(),
+                        sender: None,
+                        sender_name: None,
+                        message: packet.message.clone(),
+                        cancelled: false,
+                    })
+                    .await?;
+                    if event.cancelled {
+                        return Ok(false);
+                    }
+                    if event.message == packet.message {
+                        return Ok(true);
+                    }
+                    packet.message = event.message;
+                    if let Some(data) = packets::get_full_server_packet_buf(
+                        &packet,
+                        version,
+                        server_handle.protocol_state(),
+                    )? {
+                        client_handle.queue_packet(data, false).await?;
+                    }
+                    return Ok(false);
+                }
+                ServerPacketType::PlayerChatMessage => {
+                    if !ProxyServer::instance().chat_pipeline().has_server_chat_handlers() {
+                        return Ok(true);
+                    }
+                    let packet = PlayerChatMessage::decode(&mut Cursor::new(buffer), version)?;
+                    let content = packet.content();
+                    let event = chat_pipeline::call_server_chat(ServerChatEvent {
+                        receiver: player.clone(),
+                        sender: Some(packet.sender),
+                        sender_name: Some(packet.formatting.sender_name.clone()),
+                        message: content.clone(),
+                        cancelled: false,
+                    })
+                    .await?;
+                    if event.cancelled {
+                        return Ok(false);
+                    }
+                    if event.message == content {
+                        return Ok(true);
+                    }
+                    // the signature covers the original message, changed messages are downgraded to system chat
+                    let mut message = Text::new("<");
+                    message.add_extra(event.sender_name.unwrap_or(packet.formatting.sender_name));
+                    message.add_extra(Text::new("> "));
+                    message.add_extra(event.message);
+                    if let Some(data) = packets::get_full_server_packet_buf(
+                        &SystemChatMessage { message, pos: 0 },
+                        version,
+                        server_handle.protocol_state(),
+                    )? {
+                        client_handle.queue_packet(data, false).await?;
+                    }
+                    return Ok(false);
+                }
+                ServerPacketType::DisguisedChatMessage => {
+                    if !ProxyServer::instance().chat_pipeline().has_server_chat_handlers() {
+                        return Ok(true);
+                    }
+                    let mut packet = DisguisedChatMessage::decode(&mut Cursor::new(buffer), version)?;
+                    let event = chat_pipeline::call_server_chat(ServerChatEvent {
+                        receiver: player.clone(),
+                        sender: None,
+                        sender_name: Some(packet.formatting.sender_name.clone()),
+                        message: packet.message.clone(),
+                        cancelled: false,
+                    })
+                    .await?;
+                    if event.cancelled {
+                        return Ok(false);
+                    }
+                    if event.message == packet.message {
+                        return Ok(true);
+                    }
+                    packet.message = event.message;
+                    if let Some(data) = packets::get_full_server_packet_buf(
+                        &packet,
+                        version,
+                        server_handle.protocol_state(),
+                    )? {
+                        client_handle.queue_packet(data, false).await?;
+                    }
+                    return Ok(false);
+                }
                 ServerPacketType::Commands => {
                     let mut commands = Commands::decode(&mut Cursor::new(buffer), version)?;
 
@@ -25,6 +25,30 @@ pub enum ServerPacketType {
     SystemChatMessage,   // game
     Commands,            // game
     TabCompleteResponse, // game
+    AddResourcePack,     // config, game
+    RemoveResourcePack,  // config, game
+    ServerLinks,         // config, game
+    CustomReportDetails, // config, game
+    PlayerChatMessage,   // game
+    DisguisedChatMessage, // game
+    PlayerInfoUpdate,    // game
+    PlayerInfoRemove,    // game
+    TabListHeaderFooter, // game
+    KeepAlive,           // config, game
+    SetTitleText,        // game
+    SetSubtitleText,     // game
+    SetTitleTimes,       // game
+    ClearTitles,         // game
+    SetActionBarText,    // game
+    BossBar,             // game
+    EntitySoundEffect,   // game
+    JoinGame,            // game
+    RegistryData,        // config
+    FeatureFlags,        // config
+    UpdateTags,          // config
+    KnownPacks,          // config
+    GameEvent,           // game
+    SynchronizePlayerPosition, // game
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -45,7 +69,13 @@ pub enum ClientPacketType {
     FinishConfiguration,   // config
     ClientSettings,        // config game
     UnsignedClientCommand, // game
+    SignedClientCommand,   // game
+    MessageAcknowledgement, // game
+    ClientChatMessage,     // game
+    KeepAlive,             // config, game
     TabCompleteRequest,    // game
+    ResourcePackResponse,  // config, game
+    EditBook,              // game
 }
 
 pub struct PacketRegistry {
@@ -254,6 +284,61 @@ impl PacketRegistry {
                 (R1_20_2, 0x02)
                 (R1_20_5, 0x03)
             }
+            begin! {
+                Server, Config, AddResourcePack;
+                (R1_20_2, 0x06)
+                (R1_20_3, 0x07)
+                (R1_20_5, 0x09)
+            }
+            begin! {
+                Server, Config, RemoveResourcePack;
+                (R1_20_3, 0x06)
+                (R1_20_5, 0x08)
+            }
+            begin! {
+                Client, Config, ResourcePackResponse;
+                (R1_20_2, 0x05)
+                (R1_20_5, 0x06)
+            }
+            begin! {
+                Server, Config, CustomReportDetails;
+                (R1_21, 0x0F)
+            }
+            begin! {
+                Server, Config, ServerLinks;
+                (R1_21, 0x10)
+            }
+            begin! {
+                Server, Config, KeepAlive;
+                (R1_20_2, 0x03)
+                (R1_20_5, 0x04)
+            }
+            begin! {
+                Client, Config, KeepAlive;
+                (R1_20_2, 0x03)
+                (R1_20_5, 0x04)
+            }
+            begin! {
+                Server, Config, RegistryData;
+                (R1_20_2, 0x05)
+                (R1_20_5, 0x07)
+            }
+            begin! {
+                Server, Config, FeatureFlags;
+                (R1_20_2, 0x07)
+                (R1_20_3, 0x08)
+                (R1_20_5, 0x0C)
+            }
+            begin! {
+                Server, Config, UpdateTags;
+                (R1_20_2, 0x08)
+                (R1_20_3, 0x09)
+                (R1_20_5, 0x0D)
+            }
+            begin! {
+                Server, Config, KnownPacks;
+                (R1_20_5, 0x0E)
+            }
         }
 
         {
@@ -270,6 +355,32 @@ impl PacketRegistry {
                 (R1_20_5, 0x04)
                 (R1_21_2, 0x05)
             }
+            begin! {
+                // before 1.20.5 every command is sent with this packet
+                Client, Game, SignedClientCommand;
+                (R1_20_2, 0x04)
+                (R1_20_5, 0x05)
+                (R1_21_2, 0x06)
+            }
+            begin! {
+                Client, Game, KeepAlive;
+                (R1_20_2, 0x14)
+                (R1_20_3, 0x15)
+                (R1_20_5, 0x18)
+                (R1_21_2, 0x1A)
+            }
+            begin! {
+                // only from 1.20.2, older clients are rejected at login (version::SUPPORTED_VERSIONS)
+                Client, Game, ClientChatMessage;
+                (R1_20_2, 0x05)
+                (R1_20_5, 0x06)
+                (R1_21_2, 0x07)
+            }
+            begin! {
+                Client, Game, MessageAcknowledgement;
+                (R1_20_2, 0x03)
+                (R1_21_2, 0x04)
+            }
             begin! {
                 Client, Game, TabCompleteRequest;
                 (R1_8, 0x14)
@@ -311,6 +422,13 @@ impl PacketRegistry {
                 (R1_20_5, 0x12 )
                 (R1_21_2, 0x14 )
             }
+            begin! {
+                Client, Game, EditBook;
+                (R1_20_2, 0x10)
+                (R1_20_3, 0x11)
+                (R1_20_5, 0x14)
+                (R1_21_2, 0x16)
+            }
 
             begin! {
                 Server, Game, CookieRequest;
@@ -367,6 +485,135 @@ impl PacketRegistry {
                 (R1_20_5, 0x6C)
                 (R1_21_2, 0x73)
             }
+            begin! {
+                // only from 1.20.2, older clients are rejected at login (version::SUPPORTED_VERSIONS)
+                Server, Game, PlayerChatMessage;
+                (R1_20_2, 0x37)
+                (R1_20_5, 0x39)
+                (R1_21_2, 0x3B)
+            }
+            begin! {
+                Server, Game, DisguisedChatMessage;
+                (R1_20_2, 0x1C)
+                (R1_20_5, 0x1E)
+            }
+            begin! {
+                Server, Game, PlayerInfoUpdate;
+                (R1_20_2, 0x3C)
+                (R1_20_5, 0x3E)
+                (R1_21_2, 0x40)
+            }
+            begin! {
+                Server, Game, PlayerInfoRemove;
+                (R1_20_2, 0x3B)
+                (R1_20_5, 0x3D)
+                (R1_21_2, 0x3F)
+            }
+            begin! {
+                Server, Game, TabListHeaderFooter;
+                (R1_20_2, 0x68)
+                (R1_20_3, 0x6A)
+                (R1_20_5, 0x6D)
+                (R1_21_2, 0x74)
+            }
+            begin! {
+                Server, Game, KeepAlive;
+                (R1_20_2, 0x24)
+                (R1_20_5, 0x26)
+                (R1_21_2, 0x27)
+            }
+            begin! {
+                Server, Game, SetTitleText;
+                (R1_20_2, 0x61)
+                (R1_20_3, 0x63)
+                (R1_20_5, 0x65)
+                (R1_21_2, 0x6C)
+            }
+            begin! {
+                Server, Game, SetSubtitleText;
+                (R1_20_2, 0x5F)
+                (R1_20_3, 0x61)
+                (R1_20_5, 0x63)
+                (R1_21_2, 0x6A)
+            }
+            begin! {
+                Server, Game, SetTitleTimes;
+                (R1_20_2, 0x62)
+                (R1_20_3, 0x64)
+                (R1_20_5, 0x66)
+                (R1_21_2, 0x6D)
+            }
+            begin! {
+                Server, Game, ClearTitles;
+                (R1_20_2, 0x0F)
+            }
+            begin! {
+                Server, Game, SetActionBarText;
+                (R1_20_2, 0x48)
+                (R1_20_3, 0x4A)
+                (R1_20_5, 0x4C)
+                (R1_21_2, 0x51)
+            }
+            begin! {
+                Server, Game, BossBar;
+                (R1_20_2, 0x0A)
+            }
+            begin! {
+                Server, Game, EntitySoundEffect;
+                (R1_20_2, 0x63)
+                (R1_20_3, 0x65)
+                (R1_20_5, 0x67)
+                (R1_21_2, 0x6E)
+            }
+            begin! {
+                Server, Game, JoinGame;
+                (R1_20_2, 0x29)
+                (R1_20_5, 0x2B)
+                (R1_21_2, 0x2C)
+            }
+            begin! {
+                Server, Game, GameEvent;
+                (R1_20_2, 0x20)
+                (R1_20_5, 0x22)
+                (R1_21_2, 0x23)
+            }
+            begin! {
+                Server, Game, SynchronizePlayerPosition;
+                (R1_20_2, 0x3E)
+                (R1_20_5, 0x40)
+                (R1_21_2, 0x42)
+            }
+            begin! {
+                Server, Game, AddResourcePack;
+                (R1_20_2, 0x42)
+                (R1_20_3, 0x44)
+                (R1_20_5, 0x46)
+                (R1_21_2, 0x4B)
+            }
+            begin! {
+                Server, Game, RemoveResourcePack;
+                (R1_20_3, 0x43)
+                (R1_20_5, 0x45)
+                (R1_21_2, 0x4A)
+            }
+            begin! {
+                Client, Game, ResourcePackResponse;
+                (R1_20_2, 0x27)
+                (R1_20_3, 0x28)
+                (R1_20_5, 0x2B)
+                (R1_21_2, 0x2D)
+                (R1_21_4, 0x2F)
+            }
+            begin! {
+                Server, Game, CustomReportDetails;
+                (R1_21, 0x7A)
+                (R1_21_2, 0x81)
+            }
+            begin! {
+                Server, Game, ServerLinks;
+                (R1_21, 0x7B)
+                (R1_21_2, 0x82)
+            }
             begin! {
                 Server, Game, BundleDelimiter;
                 (R1_19_4, 0x00)
@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use super::{
+    packet_ids::{ClientPacketType, PacketRegistry},
+    packets::ProtocolState,
+    rate_limit::SlidingWindow,
+    ProxyServer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitAction {
+    /// the packet is not handled or forwarded
+    Drop,
+    Kick,
+    /// the packet is let through
+    Log,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacketLimit {
+    /// Packets per second, 0 for no limit
+    pub per_second: u32,
+    pub action: LimitAction,
+}
+
+impl PacketLimit {
+    pub fn new(per_second: u32, action: LimitAction) -> Self {
+        Self { per_second, action }
+    }
+}
+
+/// Limits for packets sent by clients, the total packet limit is ``max_packet_per_second``
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PacketLimiterConfig {
+    /// Used for the packet and byte limits, only kick or log. Dropping any packet would break
+    /// signed chat, keep alives and teleport confirmations
+    #[serde(default = "default_action", deserialize_with = "deserialize_action")]
+    pub action: LimitAction,
+    /// Bytes per second of packet data as received, compressed packets count with their compressed size, 0 for no limit
+    #[serde(default = "default_max_bytes_per_second")]
+    pub max_bytes_per_second: u64,
+    #[serde(default = "default_tab_complete")]
+    pub tab_complete: PacketLimit,
+    #[serde(default = "default_commands")]
+    pub commands: PacketLimit,
+    #[serde(default = "default_custom_payload")]
+    pub custom_payload: PacketLimit,
+    #[serde(default = "default_book_edit")]
+    pub book_edit: PacketLimit,
+}
+
+fn default_action() -> LimitAction {
+    LimitAction::Kick
+}
+
+fn deserialize_action<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LimitAction, D::Error> {
+    match LimitAction::deserialize(deserializer)? {
+        LimitAction::Drop => Err(D::Error::custom(
+            "the packet limiter action must be kick or log, drop is only allowed for single packet types",
+        )),
+        action => Ok(action),
+    }
+}
+
+fn default_max_bytes_per_second() -> u64 {
+    1 << 20
+}
+
+fn default_tab_complete() -> PacketLimit {
+    PacketLimit::new(20, LimitAction::Drop)
+}
+
+fn default_commands() -> PacketLimit {
+    PacketLimit::new(20, LimitAction::Drop)
+}
+
+fn default_custom_payload() -> PacketLimit {
+    PacketLimit::new(200, LimitAction::Drop)
+}
+
+fn default_book_edit() -> PacketLimit {
+    PacketLimit::new(5, LimitAction::Kick)
+}
+
+impl Default for PacketLimiterConfig {
+    fn default() -> Self {
+        Self {
+            action: default_action(),
+            max_bytes_per_second: default_max_bytes_per_second(),
+            tab_complete: default_tab_complete(),
+            commands: default_commands(),
+            custom_payload: default_custom_payload(),
+            book_edit: default_book_edit(),
+        }
+    }
+}
+
+impl PacketLimiterConfig {
+    fn limit(&self, packet_type: ClientPacketType) -> Option<&PacketLimit> {
+        match packet_type {
+            ClientPacketType::TabCompleteRequest => Some(&self.tab_complete),
+            ClientPacketType::UnsignedClientCommand | ClientPacketType::SignedClientCommand => {
+                Some(&self.commands)
+            }
+            ClientPacketType::ClientCustomPayload => Some(&self.custom_payload),
+            ClientPacketType::EditBook => Some(&self.book_edit),
+            _ => None,
+        }
+    }
+}
+
+pub enum Verdict {
+    Allow,
+    Drop,
+    Kick(String),
+}
+
+/// Exceeded limits with the log action are logged at most once in this time
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Packet and byte rates of one client connection
+pub struct PacketLimiter {
+    packets: SlidingWindow,
+    bytes: SlidingWindow,
+    types: HashMap<ClientPacketType, SlidingWindow>,
+    last_log: Option<Instant>,
+}
+
+impl PacketLimiter {
+    pub fn new() -> Self {
+        Self {
+            packets: SlidingWindow::new(Duration::from_secs(1)),
+            bytes: SlidingWindow::new(Duration::from_secs(1)),
+            types: HashMap::new(),
+            last_log: None,
+        }
+    }
+
+    /// Counts a packet, ``size`` is the length of the packet including its id
+    pub fn check(
+        &mut self,
+        name: &str,
+        state: ProtocolState,
+        version: i32,
+        packet_id: i32,
+        size: usize,
+    ) -> Verdict {
+        let max_packets = ProxyServer::instance().config().max_packet_per_second;
+        let config = &ProxyServer::instance().config().packet_limiter;
+        let packets = self.packets.add(1);
+        if max_packets > 0 && packets > max_packets as u64 {
+            return self.apply(name, config.action, "packets", max_packets as u64);
+        }
+        let bytes = self.bytes.add(size as u64);
+        if config.max_bytes_per_second > 0 && bytes > config.max_bytes_per_second {
+            return self.apply(name, config.action, "bytes", config.max_bytes_per_second);
+        }
+        let packet_type =
+            match PacketRegistry::instance().get_client_packet_type(state, version, packet_id) {
+                Some(packet_type) => packet_type,
+                None => return Verdict::Allow,
+            };
+        let limit = match config.limit(packet_type) {
+            Some(limit) if limit.per_second > 0 => limit,
+            _ => return Verdict::Allow,
+        };
+        let count = self
+            .types
+            .entry(packet_type)
+            .or_insert_with(|| SlidingWindow::new(Duration::from_secs(1)))
+            .add(1);
+        if count > limit.per_second as u64 {
+            let action = limit.action;
+            let limit = limit.per_second as u64;
+            return self.apply(name, action, &format!("{:?} packets", packet_type), limit);
+        }
+        Verdict::Allow
+    }
+
+    fn apply(&mut self, name: &str, action: LimitAction, what: &str, limit: u64) -> Verdict {
+        match action {
+            LimitAction::Drop => Verdict::Drop,
+            LimitAction::Kick => Verdict::Kick(format!("too many {}", what)),
+            LimitAction::Log => {
+                if self
+                    .last_log
+                    .is_none_or(|time| time.elapsed() >= LOG_INTERVAL)
+                {
+                    warn!("{} sent more than {} {} per second", name, limit, what);
+                    self.last_log = Some(Instant::now());
+                }
+                Verdict::Allow
+            }
+        }
+    }
+}
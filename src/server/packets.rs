@@ -2,10 +2,11 @@
 
 use std::{
     io::{Cursor, ErrorKind, Read, Write},
-    time::Duration,
+    ops::DerefMut,
 };
 
 use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use bytes::BufMut;
 use either::Either;
 use serde_json::Value;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -23,13 +24,12 @@ use super::{
     brigadier::Suggestions,
     compression::RefSizeLimitedReader,
     encryption::{PacketDecryption, PacketEncryption},
+    limits::{self, MAX_FRAME_SIZE},
     nbt::NbtType,
     packet_ids::{ClientPacketType, PacketRegistry, ServerPacketType},
     ProxyServer,
 };
 
-pub const PROTOCOL_READ_TIMEOUT: Duration = Duration::from_secs(30);
-
 pub const PROTOCOL_STATE_STATUS: i32 = 1;
 pub const PROTOCOL_STATE_LOGIN: i32 = 2;
 pub const PROTOCOL_STATE_TRANSFER: i32 = 3;
@@ -955,6 +955,414 @@ impl Packet for UnsignedClientCommand {
     }
 }
 
+pub struct SignedClientCommand {
+    pub command: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    pub argument_signatures: Vec<(String, Vec<u8>)>,
+    pub offset: i32,
+    pub acknowledged: [u8; 3],
+}
+
+impl ClientPacket for SignedClientCommand {
+    fn get_type(&self) -> ClientPacketType {
+        ClientPacketType::SignedClientCommand
+    }
+}
+
+impl Packet for SignedClientCommand {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let command = EncodingHelper::read_string(src, 256)?;
+        let timestamp = src.read_i64::<BE>()?;
+        let salt = src.read_i64::<BE>()?;
+        let count = VarInt::decode_simple(src)?.get();
+        if !(0..=8).contains(&count) {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Too many argument signatures"));
+        }
+        let mut argument_signatures = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = EncodingHelper::read_string(src, 16)?;
+            let mut signature = vec![0; 256];
+            src.read_exact(&mut signature)?;
+            argument_signatures.push((name, signature));
+        }
+        let offset = VarInt::decode_simple(src)?.get();
+        let mut acknowledged = [0; 3];
+        src.read_exact(&mut acknowledged)?;
+        Ok(Self {
+            command,
+            timestamp,
+            salt,
+            argument_signatures,
+            offset,
+            acknowledged,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        EncodingHelper::write_string(dst, &self.command)?;
+        dst.write_i64::<BE>(self.timestamp)?;
+        dst.write_i64::<BE>(self.salt)?;
+        VarInt(self.argument_signatures.len() as i32).encode_simple(dst)?;
+        for (name, signature) in &self.argument_signatures {
+            EncodingHelper::write_string(dst, name)?;
+            dst.write_all(signature)?;
+        }
+        VarInt(self.offset).encode_simple(dst)?;
+        dst.write_all(&self.acknowledged)?;
+        Ok(())
+    }
+}
+
+pub struct MessageAcknowledgement {
+    pub offset: i32,
+}
+
+impl ClientPacket for MessageAcknowledgement {
+    fn get_type(&self) -> ClientPacketType {
+        ClientPacketType::MessageAcknowledgement
+    }
+}
+
+impl Packet for MessageAcknowledgement {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            offset: VarInt::decode_simple(src)?.get(),
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        VarInt(self.offset).encode_simple(dst)?;
+        Ok(())
+    }
+}
+
+pub struct ClientChatMessage {
+    pub message: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    pub signature: Option<Vec<u8>>,
+    pub offset: i32,
+    pub acknowledged: [u8; 3],
+}
+
+impl ClientPacket for ClientChatMessage {
+    fn get_type(&self) -> ClientPacketType {
+        ClientPacketType::ClientChatMessage
+    }
+}
+
+impl Packet for ClientChatMessage {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let message = EncodingHelper::read_string(src, 256)?;
+        let timestamp = src.read_i64::<BE>()?;
+        let salt = src.read_i64::<BE>()?;
+        let signature = if src.read_u8()? != 0 {
+            let mut signature = vec![0; 256];
+            src.read_exact(&mut signature)?;
+            Some(signature)
+        } else {
+            None
+        };
+        let offset = VarInt::decode_simple(src)?.get();
+        let mut acknowledged = [0; 3];
+        src.read_exact(&mut acknowledged)?;
+        Ok(Self {
+            message,
+            timestamp,
+            salt,
+            signature,
+            offset,
+            acknowledged,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        EncodingHelper::write_string(dst, &self.message)?;
+        dst.write_i64::<BE>(self.timestamp)?;
+        dst.write_i64::<BE>(self.salt)?;
+        if let Some(ref signature) = self.signature {
+            dst.write_u8(1)?;
+            dst.write_all(signature)?;
+        } else {
+            dst.write_u8(0)?;
+        }
+        VarInt(self.offset).encode_simple(dst)?;
+        dst.write_all(&self.acknowledged)?;
+        Ok(())
+    }
+}
+
+pub struct ChatTypeDecoration {
+    pub translation_key: String,
+    pub parameters: Vec<i32>,
+    pub style: Either<Option<NbtType>, nbt::NamedTag>,
+}
+
+impl ChatTypeDecoration {
+    fn read<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self> {
+        let translation_key = EncodingHelper::read_string(src, 32767)?;
+        let count = VarInt::decode_simple(src)?.get();
+        if !(0..=8).contains(&count) {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Too many chat type parameters"));
+        }
+        let mut parameters = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            parameters.push(VarInt::decode_simple(src)?.get());
+        }
+        let style = nbt::read_networking_nbt(src, version)?;
+        Ok(Self {
+            translation_key,
+            parameters,
+            style,
+        })
+    }
+
+    fn write<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        EncodingHelper::write_string(dst, &self.translation_key)?;
+        VarInt(self.parameters.len() as i32).encode_simple(dst)?;
+        for parameter in &self.parameters {
+            VarInt(*parameter).encode_simple(dst)?;
+        }
+        nbt::write_networking_nbt(dst, version, &self.style)
+    }
+}
+
+pub enum ChatType {
+    Registry(i32),
+    /// since 1.20.5 the backend may send chat types that are not in the registry
+    Inline {
+        chat: ChatTypeDecoration,
+        narration: ChatTypeDecoration,
+    },
+}
+
+/// Chat type with the sender and target name, shared by player and disguised chat
+pub struct ChatFormatting {
+    pub chat_type: ChatType,
+    pub sender_name: Text,
+    pub target_name: Option<Text>,
+}
+
+impl ChatFormatting {
+    fn read<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self> {
+        let chat_type = if version >= R1_20_5 {
+            match VarInt::decode_simple(src)?.get() {
+                0 => ChatType::Inline {
+                    chat: ChatTypeDecoration::read(src, version)?,
+                    narration: ChatTypeDecoration::read(src, version)?,
+                },
+                id => ChatType::Registry(id - 1),
+            }
+        } else {
+            ChatType::Registry(VarInt::decode_simple(src)?.get())
+        };
+        let sender_name = EncodingHelper::read_text(src, version)?;
+        let target_name = if src.read_u8()? != 0 {
+            Some(EncodingHelper::read_text(src, version)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            chat_type,
+            sender_name,
+            target_name,
+        })
+    }
+
+    fn write<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        match self.chat_type {
+            ChatType::Registry(id) => {
+                let id = if version >= R1_20_5 { id + 1 } else { id };
+                VarInt(id).encode_simple(dst)?;
+            }
+            ChatType::Inline {
+                ref chat,
+                ref narration,
+            } => {
+                VarInt(0).encode_simple(dst)?;
+                chat.write(dst, version)?;
+                narration.write(dst, version)?;
+            }
+        }
+        EncodingHelper::write_text(dst, version, &self.sender_name)?;
+        if let Some(ref target_name) = self.target_name {
+            dst.write_u8(1)?;
+            EncodingHelper::write_text(dst, version, target_name)?;
+        } else {
+            dst.write_u8(0)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct PlayerChatMessage {
+    pub sender: Uuid,
+    pub index: i32,
+    pub signature: Option<Vec<u8>>,
+    pub message: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    /// id + 1 of a cached signature, or 0 followed by the full signature
+    pub previous_messages: Vec<(i32, Option<Vec<u8>>)>,
+    pub unsigned_content: Option<Text>,
+    pub filter_type: i32,
+    pub filter_mask: Vec<i64>,
+    pub formatting: ChatFormatting,
+}
+
+impl PlayerChatMessage {
+    /// What the client would display as message content
+    pub fn content(&self) -> Text {
+        self.unsigned_content
+            .clone()
+            .unwrap_or_else(|| Text::new(self.message.clone()))
+    }
+}
+
+impl ServerPacket for PlayerChatMessage {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::PlayerChatMessage
+    }
+}
+
+impl Packet for PlayerChatMessage {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let sender = EncodingHelper::read_uuid(src)?;
+        let index = VarInt::decode_simple(src)?.get();
+        let signature = if src.read_u8()? != 0 {
+            let mut signature = vec![0; 256];
+            src.read_exact(&mut signature)?;
+            Some(signature)
+        } else {
+            None
+        };
+        let message = EncodingHelper::read_string(src, 256)?;
+        let timestamp = src.read_i64::<BE>()?;
+        let salt = src.read_i64::<BE>()?;
+        let count = VarInt::decode_simple(src)?.get();
+        if !(0..=20).contains(&count) {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Too many previous messages"));
+        }
+        let mut previous_messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let id = VarInt::decode_simple(src)?.get();
+            if id == 0 {
+                let mut signature = vec![0; 256];
+                src.read_exact(&mut signature)?;
+                previous_messages.push((id, Some(signature)));
+            } else {
+                previous_messages.push((id, None));
+            }
+        }
+        let unsigned_content = if src.read_u8()? != 0 {
+            Some(EncodingHelper::read_text(src, version)?)
+        } else {
+            None
+        };
+        let filter_type = VarInt::decode_simple(src)?.get();
+        let mut filter_mask = Vec::new();
+        if filter_type == 2 {
+            let len = VarInt::decode_simple(src)?.get();
+            if !(0..=4).contains(&len) {
+                return Err(IOError::new(IOErrorKind::InvalidData, "Filter mask too big"));
+            }
+            for _ in 0..len {
+                filter_mask.push(src.read_i64::<BE>()?);
+            }
+        }
+        let formatting = ChatFormatting::read(src, version)?;
+        Ok(Self {
+            sender,
+            index,
+            signature,
+            message,
+            timestamp,
+            salt,
+            previous_messages,
+            unsigned_content,
+            filter_type,
+            filter_mask,
+            formatting,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        EncodingHelper::write_uuid(dst, &self.sender)?;
+        VarInt(self.index).encode_simple(dst)?;
+        if let Some(ref signature) = self.signature {
+            dst.write_u8(1)?;
+            dst.write_all(signature)?;
+        } else {
+            dst.write_u8(0)?;
+        }
+        EncodingHelper::write_string(dst, &self.message)?;
+        dst.write_i64::<BE>(self.timestamp)?;
+        dst.write_i64::<BE>(self.salt)?;
+        VarInt(self.previous_messages.len() as i32).encode_simple(dst)?;
+        for (id, signature) in &self.previous_messages {
+            VarInt(*id).encode_simple(dst)?;
+            if let Some(signature) = signature {
+                dst.write_all(signature)?;
+            }
+        }
+        if let Some(ref unsigned_content) = self.unsigned_content {
+            dst.write_u8(1)?;
+            EncodingHelper::write_text(dst, version, unsigned_content)?;
+        } else {
+            dst.write_u8(0)?;
+        }
+        VarInt(self.filter_type).encode_simple(dst)?;
+        if self.filter_type == 2 {
+            VarInt(self.filter_mask.len() as i32).encode_simple(dst)?;
+            for long in &self.filter_mask {
+                dst.write_i64::<BE>(*long)?;
+            }
+        }
+        self.formatting.write(dst, version)
+    }
+}
+
+pub struct DisguisedChatMessage {
+    pub message: Text,
+    pub formatting: ChatFormatting,
+}
+
+impl ServerPacket for DisguisedChatMessage {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::DisguisedChatMessage
+    }
+}
+
+impl Packet for DisguisedChatMessage {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            message: EncodingHelper::read_text(src, version)?,
+            formatting: ChatFormatting::read(src, version)?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        EncodingHelper::write_text(dst, version, &self.message)?;
+        self.formatting.write(dst, version)
+    }
+}
+
 pub struct SystemChatMessage {
     pub message: Text,
     pub pos: i32,
@@ -971,34 +1379,21 @@ impl Packet for SystemChatMessage {
     where
         Self: Sized,
     {
-        let nbt = nbt::read_networking_nbt(src, version)?;
-        if let Some(nbt_tag) = nbt.left() {
-            if let Some(nbt_tag) = nbt_tag {
-                let json = nbt_tag.to_json();
-                let text = crate::chat::deserialize_json(&json)
-                    .map_err(|err| IOError::new(ErrorKind::InvalidData, err))?;
-                let pos = if version >= R1_19_1 {
-                    if src.read_u8()? != 0 {
-                        2
-                    } else {
-                        0
-                    }
-                } else {
-                    VarInt::decode(src, 5)?.get()
-                };
-                Ok(Self { message: text, pos })
+        let message = EncodingHelper::read_text(src, version)?;
+        let pos = if version >= R1_19_1 {
+            if src.read_u8()? != 0 {
+                2
             } else {
-                Err(IOError::new(ErrorKind::InvalidData, "invalid nbt type"))
+                0
             }
         } else {
-            todo!()
-        }
+            VarInt::decode(src, 5)?.get()
+        };
+        Ok(Self { message, pos })
     }
 
     fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
-        let json = crate::chat::serialize_json(&self.message);
-        let nbt = NbtType::from_json(&json)?;
-        nbt::write_networking_nbt(dst, version, &Either::Left(Some(nbt)))?;
+        EncodingHelper::write_text(dst, version, &self.message)?;
         if version >= R1_19_1 {
             dst.write_u8(self.pos as u8)?;
         } else {
@@ -1140,76 +1535,1065 @@ impl Packet for TabCompleteResponse {
     }
 }
 
-pub async fn read_and_decode_packet<R: AsyncRead + Unpin + ?Sized>(
-    src: &mut R,
-    dest_buf: &mut Vec<u8>,
-    temp_buf: &mut Vec<u8>,
-    compression: i32,
-    decryption: &mut Option<PacketDecryption>,
-) -> IOResult<()> {
-    tokio::time::timeout(PROTOCOL_READ_TIMEOUT, async move {
-        let size = match decryption {
-            Some(decrypt) => VarInt::decode_encrypted_async(src, 3, decrypt).await,
-            None => VarInt::decode_async(src, 3).await,
-        }?
-        .get() as usize;
+pub struct AddResourcePack {
+    pub id: Option<Uuid>,
+    pub url: String,
+    pub hash: String,
+    pub forced: bool,
+    pub prompt: Option<Text>,
+}
+
+impl ServerPacket for AddResourcePack {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::AddResourcePack
+    }
+}
 
-        temp_buf.clear();
-        dest_buf.clear();
-        if compression != -1 {
-            temp_buf.resize(size, 0);
-            src.read_exact(temp_buf).await?;
+impl Packet for AddResourcePack {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let id = if version >= R1_20_3 {
+            Some(EncodingHelper::read_uuid(src)?)
         } else {
-            dest_buf.resize(size, 0);
-            src.read_exact(dest_buf).await?;
-        }
+            None
+        };
+        let url = EncodingHelper::read_string(src, 32767)?;
+        let hash = EncodingHelper::read_string(src, 40)?;
+        let forced = src.read_u8()? != 0;
+        let prompt = if src.read_u8()? != 0 {
+            Some(EncodingHelper::read_text(src, version)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            id,
+            url,
+            hash,
+            forced,
+            prompt,
+        })
+    }
 
-        if let Some(decrypt) = decryption {
-            if compression != -1 {
-                decrypt.decrypt(temp_buf);
-            } else {
-                decrypt.decrypt(dest_buf);
-            }
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        if version >= R1_20_3 {
+            EncodingHelper::write_uuid(dst, self.id.as_ref().unwrap())?;
+        }
+        EncodingHelper::write_string(dst, &self.url)?;
+        EncodingHelper::write_string(dst, &self.hash)?;
+        dst.write_u8(self.forced as u8)?;
+        if let Some(ref prompt) = self.prompt {
+            dst.write_u8(1)?;
+            EncodingHelper::write_text(dst, version, prompt)?;
+        } else {
+            dst.write_u8(0)?;
         }
+        Ok(())
+    }
+}
+
+pub struct RemoveResourcePack {
+    pub id: Option<Uuid>,
+}
+
+impl ServerPacket for RemoveResourcePack {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::RemoveResourcePack
+    }
+}
+
+impl Packet for RemoveResourcePack {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let id = if src.read_u8()? != 0 {
+            Some(EncodingHelper::read_uuid(src)?)
+        } else {
+            None
+        };
+        Ok(Self { id })
+    }
 
-        if compression != -1 {
-            super::compression::decompress(temp_buf, dest_buf)?;
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        if let Some(ref id) = self.id {
+            dst.write_u8(1)?;
+            EncodingHelper::write_uuid(dst, id)?;
+        } else {
+            dst.write_u8(0)?;
         }
-        temp_buf.clear();
+        Ok(())
+    }
+}
 
-        Ok::<_, IOError>(())
-    })
-    .await??;
-    Ok(())
+pub struct ResourcePackResponse {
+    pub id: Option<Uuid>,
+    pub result: i32,
 }
 
-pub async fn encode_and_send_packet<W: AsyncWrite + Unpin + ?Sized>(
-    dst: &mut W,
-    write_buf: &[u8],
-    temp_buf: &mut Vec<u8>,
-    compression: i32,
-    encryption: &mut Option<PacketEncryption>,
-) -> IOResult<()> {
-    temp_buf.clear();
-    if compression >= 0 {
-        super::compression::compress(write_buf, compression, temp_buf)?;
-    } else {
-        temp_buf.extend_from_slice(write_buf);
+impl ClientPacket for ResourcePackResponse {
+    fn get_type(&self) -> ClientPacketType {
+        ClientPacketType::ResourcePackResponse
     }
+}
 
+impl Packet for ResourcePackResponse {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let id = if version >= R1_20_3 {
+            Some(EncodingHelper::read_uuid(src)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            id,
+            result: VarInt::decode_simple(src)?.get(),
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        if version >= R1_20_3 {
+            EncodingHelper::write_uuid(dst, self.id.as_ref().unwrap())?;
+        }
+        VarInt(self.result).encode_simple(dst)?;
+        Ok(())
+    }
+}
+
+pub enum ServerLinkLabel {
+    BuiltIn(i32),
+    Custom(Text),
+}
+
+pub struct ServerLink {
+    pub label: ServerLinkLabel,
+    pub url: String,
+}
+
+pub struct ServerLinks {
+    pub links: Vec<ServerLink>,
+}
+
+impl ServerPacket for ServerLinks {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::ServerLinks
+    }
+}
+
+impl Packet for ServerLinks {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let count = VarInt::decode_simple(src)?.get();
+        if !(0..=1024).contains(&count) {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Too many server links"));
+        }
+        let mut links = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let label = if src.read_u8()? != 0 {
+                ServerLinkLabel::BuiltIn(VarInt::decode_simple(src)?.get())
+            } else {
+                ServerLinkLabel::Custom(EncodingHelper::read_text(src, version)?)
+            };
+            let url = EncodingHelper::read_string(src, 32767)?;
+            links.push(ServerLink { label, url });
+        }
+        Ok(Self { links })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        VarInt(self.links.len() as i32).encode_simple(dst)?;
+        for link in &self.links {
+            match link.label {
+                ServerLinkLabel::BuiltIn(id) => {
+                    dst.write_u8(1)?;
+                    VarInt(id).encode_simple(dst)?;
+                }
+                ServerLinkLabel::Custom(ref text) => {
+                    dst.write_u8(0)?;
+                    EncodingHelper::write_text(dst, version, text)?;
+                }
+            }
+            EncodingHelper::write_string(dst, &link.url)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct CustomReportDetails {
+    pub details: Vec<(String, String)>,
+}
+
+impl ServerPacket for CustomReportDetails {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::CustomReportDetails
+    }
+}
+
+impl Packet for CustomReportDetails {
+    fn decode<R: Read + ?Sized>(src: &mut R, _version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let count = VarInt::decode_simple(src)?.get();
+        if !(0..=32).contains(&count) {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Too many report details"));
+        }
+        let mut details = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let title = EncodingHelper::read_string(src, 128)?;
+            let description = EncodingHelper::read_string(src, 4096)?;
+            details.push((title, description));
+        }
+        Ok(Self { details })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _version: i32) -> IOResult<()> {
+        VarInt(self.details.len() as i32).encode_simple(dst)?;
+        for (title, description) in &self.details {
+            EncodingHelper::write_string(dst, title)?;
+            EncodingHelper::write_string(dst, description)?;
+        }
+        Ok(())
+    }
+}
+
+pub const PLAYER_INFO_ADD_PLAYER: u8 = 0x01;
+pub const PLAYER_INFO_INITIALIZE_CHAT: u8 = 0x02;
+pub const PLAYER_INFO_UPDATE_GAME_MODE: u8 = 0x04;
+pub const PLAYER_INFO_UPDATE_LISTED: u8 = 0x08;
+pub const PLAYER_INFO_UPDATE_LATENCY: u8 = 0x10;
+pub const PLAYER_INFO_UPDATE_DISPLAY_NAME: u8 = 0x20;
+/// since 1.21.2
+pub const PLAYER_INFO_UPDATE_LIST_ORDER: u8 = 0x40;
+/// since 1.21.4
+pub const PLAYER_INFO_UPDATE_HAT: u8 = 0x80;
+
+pub struct ChatSession {
+    pub session_id: Uuid,
+    pub expiry: i64,
+    pub public_key: Vec<u8>,
+    pub key_signature: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct PlayerInfoEntry {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub chat_session: Option<ChatSession>,
+    pub game_mode: i32,
+    pub listed: bool,
+    pub latency: i32,
+    pub display_name: Option<Text>,
+    pub list_order: i32,
+    pub show_hat: bool,
+}
+
+pub struct PlayerInfoUpdate {
+    pub actions: u8,
+    pub entries: Vec<PlayerInfoEntry>,
+}
+
+impl PlayerInfoUpdate {
+    fn supported_actions(version: i32) -> u8 {
+        if version >= R1_21_4 {
+            0xFF
+        } else if version >= R1_21_2 {
+            0x7F
+        } else {
+            0x3F
+        }
+    }
+}
+
+impl ServerPacket for PlayerInfoUpdate {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::PlayerInfoUpdate
+    }
+}
+
+impl Packet for PlayerInfoUpdate {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let actions = src.read_u8()? & Self::supported_actions(version);
+        let count = VarInt::decode_simple(src)?.get();
+        if count < 0 {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Negative player info count"));
+        }
+        let mut entries = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            let mut entry = PlayerInfoEntry {
+                uuid: EncodingHelper::read_uuid(src)?,
+                ..Default::default()
+            };
+            if actions & PLAYER_INFO_ADD_PLAYER != 0 {
+                entry.name = EncodingHelper::read_string(src, 16)?;
+                let num_props = VarInt::decode_simple(src)?.get();
+                if !(0..=16).contains(&num_props) {
+                    return Err(IOError::new(IOErrorKind::InvalidData, "Too many properties"));
+                }
+                for _ in 0..num_props {
+                    let name = EncodingHelper::read_string(src, 64)?;
+                    let value = EncodingHelper::read_string(src, 32767)?;
+                    let signature = if src.read_u8()? != 0 {
+                        Some(EncodingHelper::read_string(src, 1024)?)
+                    } else {
+                        None
+                    };
+                    entry.properties.push(Property {
+                        name,
+                        value,
+                        signature,
+                    });
+                }
+            }
+            if actions & PLAYER_INFO_INITIALIZE_CHAT != 0 && src.read_u8()? != 0 {
+                entry.chat_session = Some(ChatSession {
+                    session_id: EncodingHelper::read_uuid(src)?,
+                    expiry: src.read_i64::<BE>()?,
+                    public_key: EncodingHelper::read_byte_array(src, 512)?,
+                    key_signature: EncodingHelper::read_byte_array(src, 4096)?,
+                });
+            }
+            if actions & PLAYER_INFO_UPDATE_GAME_MODE != 0 {
+                entry.game_mode = VarInt::decode_simple(src)?.get();
+            }
+            if actions & PLAYER_INFO_UPDATE_LISTED != 0 {
+                entry.listed = src.read_u8()? != 0;
+            }
+            if actions & PLAYER_INFO_UPDATE_LATENCY != 0 {
+                entry.latency = VarInt::decode_simple(src)?.get();
+            }
+            if actions & PLAYER_INFO_UPDATE_DISPLAY_NAME != 0 && src.read_u8()? != 0 {
+                entry.display_name = Some(EncodingHelper::read_text(src, version)?);
+            }
+            if actions & PLAYER_INFO_UPDATE_LIST_ORDER != 0 {
+                entry.list_order = VarInt::decode_simple(src)?.get();
+            }
+            if actions & PLAYER_INFO_UPDATE_HAT != 0 {
+                entry.show_hat = src.read_u8()? != 0;
+            }
+            entries.push(entry);
+        }
+        Ok(Self { actions, entries })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        let actions = self.actions & Self::supported_actions(version);
+        dst.write_u8(actions)?;
+        VarInt(self.entries.len() as i32).encode_simple(dst)?;
+        for entry in &self.entries {
+            EncodingHelper::write_uuid(dst, &entry.uuid)?;
+            if actions & PLAYER_INFO_ADD_PLAYER != 0 {
+                EncodingHelper::write_string(dst, &entry.name)?;
+                VarInt(entry.properties.len() as i32).encode_simple(dst)?;
+                for property in &entry.properties {
+                    EncodingHelper::write_string(dst, &property.name)?;
+                    EncodingHelper::write_string(dst, &property.value)?;
+                    if let Some(ref signature) = property.signature {
+                        dst.write_u8(1)?;
+                        EncodingHelper::write_string(dst, signature)?;
+                    } else {
+                        dst.write_u8(0)?;
+                    }
+                }
+            }
+            if actions & PLAYER_INFO_INITIALIZE_CHAT != 0 {
+                if let Some(ref session) = entry.chat_session {
+                    dst.write_u8(1)?;
+                    EncodingHelper::write_uuid(dst, &session.session_id)?;
+                    dst.write_i64::<BE>(session.expiry)?;
+                    EncodingHelper::write_byte_array(dst, &session.public_key)?;
+                    EncodingHelper::write_byte_array(dst, &session.key_signature)?;
+                } else {
+                    dst.write_u8(0)?;
+                }
+            }
+            if actions & PLAYER_INFO_UPDATE_GAME_MODE != 0 {
+                VarInt(entry.game_mode).encode_simple(dst)?;
+            }
+            if actions & PLAYER_INFO_UPDATE_LISTED != 0 {
+                dst.write_u8(entry.listed as u8)?;
+            }
+            if actions & PLAYER_INFO_UPDATE_LATENCY != 0 {
+                VarInt(entry.latency).encode_simple(dst)?;
+            }
+            if actions & PLAYER_INFO_UPDATE_DISPLAY_NAME != 0 {
+                if let Some(ref display_name) = entry.display_name {
+                    dst.write_u8(1)?;
+                    EncodingHelper::write_text(dst, version, display_name)?;
+                } else {
+                    dst.write_u8(0)?;
+                }
+            }
+            if actions & PLAYER_INFO_UPDATE_LIST_ORDER != 0 {
+                VarInt(entry.list_order).encode_simple(dst)?;
+            }
+            if actions & PLAYER_INFO_UPDATE_HAT != 0 {
+                dst.write_u8(entry.show_hat as u8)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct PlayerInfoRemove {
+    pub uuids: Vec<Uuid>,
+}
+
+impl ServerPacket for PlayerInfoRemove {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::PlayerInfoRemove
+    }
+}
+
+impl Packet for PlayerInfoRemove {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let count = VarInt::decode_simple(src)?.get();
+        if count < 0 {
+            return Err(IOError::new(IOErrorKind::InvalidData, "Negative player info count"));
+        }
+        let mut uuids = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            uuids.push(EncodingHelper::read_uuid(src)?);
+        }
+        Ok(Self { uuids })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        VarInt(self.uuids.len() as i32).encode_simple(dst)?;
+        for uuid in &self.uuids {
+            EncodingHelper::write_uuid(dst, uuid)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct TabListHeaderFooter {
+    pub header: Text,
+    pub footer: Text,
+}
+
+impl ServerPacket for TabListHeaderFooter {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::TabListHeaderFooter
+    }
+}
+
+impl Packet for TabListHeaderFooter {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            header: EncodingHelper::read_text(src, version)?,
+            footer: EncodingHelper::read_text(src, version)?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        EncodingHelper::write_text(dst, version, &self.header)?;
+        EncodingHelper::write_text(dst, version, &self.footer)
+    }
+}
+
+pub struct ServerKeepAlive {
+    pub id: i64,
+}
+
+impl ServerPacket for ServerKeepAlive {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::KeepAlive
+    }
+}
+
+impl Packet for ServerKeepAlive {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            id: src.read_i64::<BE>()?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_i64::<BE>(self.id)?;
+        Ok(())
+    }
+}
+
+pub struct ClientKeepAlive {
+    pub id: i64,
+}
+
+impl ClientPacket for ClientKeepAlive {
+    fn get_type(&self) -> ClientPacketType {
+        ClientPacketType::KeepAlive
+    }
+}
+
+impl Packet for ClientKeepAlive {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            id: src.read_i64::<BE>()?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_i64::<BE>(self.id)?;
+        Ok(())
+    }
+}
+
+macro_rules! text_packet {
+    ($name:ident) => {
+        pub struct $name {
+            pub text: Text,
+        }
+
+        impl ServerPacket for $name {
+            fn get_type(&self) -> ServerPacketType {
+                ServerPacketType::$name
+            }
+        }
+
+        impl Packet for $name {
+            fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+            where
+                Self: Sized,
+            {
+                Ok(Self {
+                    text: EncodingHelper::read_text(src, version)?,
+                })
+            }
+
+            fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+                EncodingHelper::write_text(dst, version, &self.text)
+            }
+        }
+    };
+}
+
+text_packet!(SetTitleText);
+text_packet!(SetSubtitleText);
+text_packet!(SetActionBarText);
+
+pub struct SetTitleTimes {
+    pub fade_in: i32,
+    pub stay: i32,
+    pub fade_out: i32,
+}
+
+impl ServerPacket for SetTitleTimes {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::SetTitleTimes
+    }
+}
+
+impl Packet for SetTitleTimes {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            fade_in: src.read_i32::<BE>()?,
+            stay: src.read_i32::<BE>()?,
+            fade_out: src.read_i32::<BE>()?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_i32::<BE>(self.fade_in)?;
+        dst.write_i32::<BE>(self.stay)?;
+        dst.write_i32::<BE>(self.fade_out)?;
+        Ok(())
+    }
+}
+
+pub struct ClearTitles {
+    /// also resets the title times
+    pub reset: bool,
+}
+
+impl ServerPacket for ClearTitles {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::ClearTitles
+    }
+}
+
+impl Packet for ClearTitles {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            reset: src.read_u8()? != 0,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_u8(self.reset as u8)?;
+        Ok(())
+    }
+}
+
+pub enum BossBarAction {
+    Add {
+        title: Text,
+        health: f32,
+        color: i32,
+        division: i32,
+        flags: u8,
+    },
+    Remove,
+    UpdateHealth(f32),
+    UpdateTitle(Text),
+    UpdateStyle {
+        color: i32,
+        division: i32,
+    },
+    UpdateFlags(u8),
+}
+
+pub struct BossBarPacket {
+    pub uuid: Uuid,
+    pub action: BossBarAction,
+}
+
+impl ServerPacket for BossBarPacket {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::BossBar
+    }
+}
+
+impl Packet for BossBarPacket {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let uuid = EncodingHelper::read_uuid(src)?;
+        let action = match VarInt::decode_simple(src)?.get() {
+            0 => BossBarAction::Add {
+                title: EncodingHelper::read_text(src, version)?,
+                health: src.read_f32::<BE>()?,
+                color: VarInt::decode_simple(src)?.get(),
+                division: VarInt::decode_simple(src)?.get(),
+                flags: src.read_u8()?,
+            },
+            1 => BossBarAction::Remove,
+            2 => BossBarAction::UpdateHealth(src.read_f32::<BE>()?),
+            3 => BossBarAction::UpdateTitle(EncodingHelper::read_text(src, version)?),
+            4 => BossBarAction::UpdateStyle {
+                color: VarInt::decode_simple(src)?.get(),
+                division: VarInt::decode_simple(src)?.get(),
+            },
+            5 => BossBarAction::UpdateFlags(src.read_u8()?),
+            action => {
+                return Err(IOError::new(
+                    IOErrorKind::InvalidData,
+                    format!("Invalid boss bar action {}", action),
+                ))
+            }
+        };
+        Ok(Self { uuid, action })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        EncodingHelper::write_uuid(dst, &self.uuid)?;
+        match self.action {
+            BossBarAction::Add {
+                ref title,
+                health,
+                color,
+                division,
+                flags,
+            } => {
+                VarInt(0).encode_simple(dst)?;
+                EncodingHelper::write_text(dst, version, title)?;
+                dst.write_f32::<BE>(health)?;
+                VarInt(color).encode_simple(dst)?;
+                VarInt(division).encode_simple(dst)?;
+                dst.write_u8(flags)?;
+            }
+            BossBarAction::Remove => {
+                VarInt(1).encode_simple(dst)?;
+            }
+            BossBarAction::UpdateHealth(health) => {
+                VarInt(2).encode_simple(dst)?;
+                dst.write_f32::<BE>(health)?;
+            }
+            BossBarAction::UpdateTitle(ref title) => {
+                VarInt(3).encode_simple(dst)?;
+                EncodingHelper::write_text(dst, version, title)?;
+            }
+            BossBarAction::UpdateStyle { color, division } => {
+                VarInt(4).encode_simple(dst)?;
+                VarInt(color).encode_simple(dst)?;
+                VarInt(division).encode_simple(dst)?;
+            }
+            BossBarAction::UpdateFlags(flags) => {
+                VarInt(5).encode_simple(dst)?;
+                dst.write_u8(flags)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCategory {
+    Master,
+    Music,
+    Record,
+    Weather,
+    Block,
+    Hostile,
+    Neutral,
+    Player,
+    Ambient,
+    Voice,
+}
+
+/// A sound played at the position of an entity, the proxy uses the entity of the player
+pub struct EntitySoundEffect {
+    /// the sound is always sent by name, so custom sounds of resource packs work too
+    pub sound: String,
+    pub fixed_range: Option<f32>,
+    pub category: SoundCategory,
+    pub entity_id: i32,
+    pub volume: f32,
+    pub pitch: f32,
+    pub seed: i64,
+}
+
+impl ServerPacket for EntitySoundEffect {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::EntitySoundEffect
+    }
+}
+
+impl Packet for EntitySoundEffect {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        if VarInt::decode_simple(src)?.get() != 0 {
+            return Err(IOError::new(
+                IOErrorKind::InvalidData,
+                "Only sounds sent by name are supported",
+            ));
+        }
+        let sound = EncodingHelper::read_string(src, 32767)?;
+        let fixed_range = if src.read_u8()? != 0 {
+            Some(src.read_f32::<BE>()?)
+        } else {
+            None
+        };
+        let category = match VarInt::decode_simple(src)?.get() {
+            0 => SoundCategory::Master,
+            1 => SoundCategory::Music,
+            2 => SoundCategory::Record,
+            3 => SoundCategory::Weather,
+            4 => SoundCategory::Block,
+            5 => SoundCategory::Hostile,
+            6 => SoundCategory::Neutral,
+            7 => SoundCategory::Player,
+            8 => SoundCategory::Ambient,
+            9 => SoundCategory::Voice,
+            _ => return Err(IOError::new(IOErrorKind::InvalidData, "Invalid sound category")),
+        };
+        Ok(Self {
+            sound,
+            fixed_range,
+            category,
+            entity_id: VarInt::decode_simple(src)?.get(),
+            volume: src.read_f32::<BE>()?,
+            pitch: src.read_f32::<BE>()?,
+            seed: src.read_i64::<BE>()?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        VarInt(0).encode_simple(dst)?;
+        EncodingHelper::write_string(dst, &self.sound)?;
+        if let Some(range) = self.fixed_range {
+            dst.write_u8(1)?;
+            dst.write_f32::<BE>(range)?;
+        } else {
+            dst.write_u8(0)?;
+        }
+        VarInt(self.category as i32).encode_simple(dst)?;
+        VarInt(self.entity_id).encode_simple(dst)?;
+        dst.write_f32::<BE>(self.volume)?;
+        dst.write_f32::<BE>(self.pitch)?;
+        dst.write_i64::<BE>(self.seed)?;
+        Ok(())
+    }
+}
+
+/// Only the entity id is decoded, the rest is kept as is
+pub struct JoinGame {
+    pub entity_id: i32,
+    pub data: Vec<u8>,
+}
+
+impl ServerPacket for JoinGame {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::JoinGame
+    }
+}
+
+impl Packet for JoinGame {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        let entity_id = src.read_i32::<BE>()?;
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Ok(Self { entity_id, data })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_i32::<BE>(self.entity_id)?;
+        dst.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+pub const GAME_EVENT_START_WAITING_FOR_CHUNKS: u8 = 13;
+
+pub struct GameEvent {
+    pub event: u8,
+    pub value: f32,
+}
+
+impl ServerPacket for GameEvent {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::GameEvent
+    }
+}
+
+impl Packet for GameEvent {
+    fn decode<R: Read + ?Sized>(src: &mut R, _: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            event: src.read_u8()?,
+            value: src.read_f32::<BE>()?,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, _: i32) -> IOResult<()> {
+        dst.write_u8(self.event)?;
+        dst.write_f32::<BE>(self.value)?;
+        Ok(())
+    }
+}
+
+/// Teleports the player to an absolute position, relative flags are not supported
+pub struct SynchronizePlayerPosition {
+    pub teleport_id: i32,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl ServerPacket for SynchronizePlayerPosition {
+    fn get_type(&self) -> ServerPacketType {
+        ServerPacketType::SynchronizePlayerPosition
+    }
+}
+
+impl Packet for SynchronizePlayerPosition {
+    fn decode<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Self>
+    where
+        Self: Sized,
+    {
+        if version >= R1_21_2 {
+            let teleport_id = VarInt::decode_simple(src)?.get();
+            let (x, y, z) = (src.read_f64::<BE>()?, src.read_f64::<BE>()?, src.read_f64::<BE>()?);
+            // velocity
+            for _ in 0..3 {
+                src.read_f64::<BE>()?;
+            }
+            let (yaw, pitch) = (src.read_f32::<BE>()?, src.read_f32::<BE>()?);
+            src.read_i32::<BE>()?;
+            return Ok(Self {
+                teleport_id,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+            });
+        }
+        let (x, y, z) = (src.read_f64::<BE>()?, src.read_f64::<BE>()?, src.read_f64::<BE>()?);
+        let (yaw, pitch) = (src.read_f32::<BE>()?, src.read_f32::<BE>()?);
+        src.read_u8()?;
+        Ok(Self {
+            teleport_id: VarInt::decode_simple(src)?.get(),
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+        })
+    }
+
+    fn encode<W: Write + ?Sized>(&self, dst: &mut W, version: i32) -> IOResult<()> {
+        if version >= R1_21_2 {
+            VarInt(self.teleport_id).encode_simple(dst)?;
+        }
+        dst.write_f64::<BE>(self.x)?;
+        dst.write_f64::<BE>(self.y)?;
+        dst.write_f64::<BE>(self.z)?;
+        if version >= R1_21_2 {
+            for _ in 0..3 {
+                dst.write_f64::<BE>(0.0)?;
+            }
+        }
+        dst.write_f32::<BE>(self.yaw)?;
+        dst.write_f32::<BE>(self.pitch)?;
+        if version >= R1_21_2 {
+            dst.write_i32::<BE>(0)?;
+        } else {
+            dst.write_u8(0)?;
+            VarInt(self.teleport_id).encode_simple(dst)?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn read_and_decode_packet<R: AsyncRead + Unpin + ?Sized>(
+    src: &mut R,
+    dest_buf: &mut Vec<u8>,
+    temp_buf: &mut Vec<u8>,
+    compression: i32,
+    decryption: &mut Option<PacketDecryption>,
+) -> IOResult<()> {
+    read_and_decode_packet_limited(
+        src,
+        dest_buf,
+        temp_buf,
+        compression,
+        decryption,
+        MAX_FRAME_SIZE,
+    )
+    .await
+}
+
+/// Reads a packet whose frame may not be larger than ``max_frame_size``, used for packets sent by clients
+pub async fn read_and_decode_packet_limited<R: AsyncRead + Unpin + ?Sized>(
+    src: &mut R,
+    dest_buf: &mut Vec<u8>,
+    temp_buf: &mut Vec<u8>,
+    compression: i32,
+    decryption: &mut Option<PacketDecryption>,
+    max_frame_size: usize,
+) -> IOResult<()> {
+    dest_buf.clear();
+    if compression == -1 {
+        return read_frame(src, dest_buf, decryption, max_frame_size).await;
+    }
+    temp_buf.clear();
+    read_frame(src, temp_buf, decryption, max_frame_size).await?;
+    super::compression::decompress(temp_buf, dest_buf)?;
+    temp_buf.clear();
+    Ok(())
+}
+
+/// Reads and decrypts a frame without its length prefix and appends it to ``dest_buf``, it is still compressed if compression is enabled
+pub async fn read_frame<R, B>(
+    src: &mut R,
+    dest_buf: &mut B,
+    decryption: &mut Option<PacketDecryption>,
+    max_frame_size: usize,
+) -> IOResult<()>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    B: BufMut + DerefMut<Target = [u8]>,
+{
+    tokio::time::timeout(limits::limits().read_timeout(), async move {
+        let size = match decryption {
+            Some(decrypt) => VarInt::decode_encrypted_async(src, 3, decrypt).await,
+            None => VarInt::decode_async(src, 3).await,
+        }?
+        .get() as usize;
+        if size > max_frame_size {
+            return Err(limits::limit_error(format!(
+                "Frame of {} bytes exceeds the limit of {} bytes",
+                size, max_frame_size
+            )));
+        }
+
+        let start = dest_buf.len();
+        dest_buf.put_bytes(0, size);
+        let frame = &mut dest_buf[start..];
+        src.read_exact(frame).await?;
+
+        if let Some(decrypt) = decryption {
+            decrypt.decrypt(frame);
+        }
+
+        Ok::<_, IOError>(())
+    })
+    .await??;
+    Ok(())
+}
+
+pub async fn encode_and_send_packet<W: AsyncWrite + Unpin + ?Sized>(
+    dst: &mut W,
+    write_buf: &[u8],
+    temp_buf: &mut Vec<u8>,
+    compression: i32,
+    encryption: &mut Option<PacketEncryption>,
+) -> IOResult<()> {
+    temp_buf.clear();
+    if compression >= 0 {
+        super::compression::compress(write_buf, compression, temp_buf)?;
+    } else {
+        temp_buf.extend_from_slice(write_buf);
+    }
+    write_frame(dst, temp_buf, encryption).await
+}
+
+/// Writes a frame with its length, both are encrypted in place
+async fn write_frame<W: AsyncWrite + Unpin + ?Sized>(
+    dst: &mut W,
+    frame: &mut [u8],
+    encryption: &mut Option<PacketEncryption>,
+) -> IOResult<()> {
     if let Some(encryption) = encryption {
         let mut varint_buf = [0u8; 3];
         let mut varint_writer = Cursor::new(varint_buf.as_mut_slice());
-        let varint_len = VarInt(temp_buf.len() as i32).encode(&mut varint_writer, 3)?;
+        let varint_len = VarInt(frame.len() as i32).encode(&mut varint_writer, 3)?;
 
         encryption.encrypt(&mut varint_buf[..varint_len]);
-        encryption.encrypt(temp_buf);
+        encryption.encrypt(frame);
 
         dst.write_all(&varint_buf[..varint_len]).await?;
-        dst.write_all(temp_buf).await?;
+        dst.write_all(frame).await?;
     } else {
-        VarInt(temp_buf.len() as i32).encode_async(dst, 3).await?;
-        dst.write_all(temp_buf).await?;
+        VarInt(frame.len() as i32).encode_async(dst, 3).await?;
+        dst.write_all(frame).await?;
     }
     Ok(())
 }
@@ -1,27 +1,41 @@
 use log::{debug, error, info, warn};
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::{
     io::Cursor,
     net::SocketAddr,
     ops::DerefMut,
     sync::{
-        atomic::{AtomicBool, AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering},
         Arc,
     },
-    time::SystemTime,
+    time::Instant,
 };
 use std::time::Duration;
 use tokio::{
-    net::{tcp::OwnedReadHalf, TcpStream},
+    net::TcpStream,
     sync::{mpsc::Sender, Mutex, Notify, RwLock},
-    task::AbortHandle,
+    task::{AbortHandle, JoinHandle},
 };
 use tokio::time::sleep;
+use bytes::{BufMut, Bytes};
 use uuid::Uuid;
 use super::{
+    boss_bar::BossBar,
+    buffer_pool::BufferPool,
+    chat_pipeline,
     encryption::{PacketDecryption, PacketEncryption},
+    health,
+    compression,
+    limbo::{self, LimboCapture},
+    limits,
+    network::{BatchWriter, ReadHalf, SendQueue, Stream, WriteHalf, TOO_SLOW},
     packet_handler::ClientPacketHandler,
+    packet_limiter::{PacketLimiter, Verdict},
     packets::{ClientSettings, PlayerPublicKey, ProtocolState},
+    resource_pack::{self, ResourcePackTracker},
+    server_group, server_links,
+    tab_list::{self, TabListTracker},
     ProxyServer,
 };
 use crate::server::packets::ClientCustomPayload;
@@ -30,8 +44,8 @@ use crate::{
     auth::LoginResult,
     chat::Text,
     server::{
-        packet_ids::{PacketRegistry, ServerPacketType},
-        packets::{self, encode_and_send_packet, read_and_decode_packet, Kick},
+        packet_ids::{ClientPacketType, PacketRegistry, ServerPacketType},
+        packets::{self, read_frame, Kick, Packet, SignedClientCommand},
         ProxiedPlayer,
     },
     util::{Handle, VarInt, WeakHandle},
@@ -45,6 +59,9 @@ pub(crate) struct ProxyingData {
     pub player_public_key: Option<PlayerPublicKey>,
     pub protocol_state: ProtocolState,
     pub address: SocketAddr,
+    /// address of the proxy the client connected to
+    pub local_address: SocketAddr,
+    pub virtual_host: String,
 }
 
 pub(crate) struct PlayerSyncData {
@@ -53,6 +70,16 @@ pub(crate) struct PlayerSyncData {
     pub game_ack_notify: Notify,
     pub client_settings: Mutex<Option<ClientSettings>>,
     pub brand_packet: Mutex<Option<ClientCustomPayload>>,
+    pub resource_packs: Mutex<ResourcePackTracker>,
+    pub tab_list: Mutex<TabListTracker>,
+    /// round trip time of the last keep alive in milliseconds
+    pub ping: AtomicI32,
+    pub pending_keep_alive: Mutex<Option<(i64, Instant)>>,
+    /// entity id the current backend assigned to the player
+    pub entity_id: AtomicI32,
+    pub boss_bars: Mutex<HashMap<Uuid, BossBar>>,
+    pub in_limbo: AtomicBool,
+    pub limbo_capture: Mutex<LimboCapture>,
 }
 
 pub struct ClientHandle {
@@ -77,127 +104,16 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
     let data_login_result = data.login_result.clone();
     let data_player_public_key = data.player_public_key.clone();
 
-    let (mut encryption, decryption) = match data.encryption {
+    let (encryption, decryption) = match data.encryption {
         Some(encryption) => (Some(encryption.0), Some(encryption.1)),
         None => (None, None),
     };
 
-    let (read, mut write) = stream.into_split();
+    let (read, write) = Stream::Tcp(stream).into_split();
     let compression_threshold = data.compression_threshold;
 
-    let (sender, mut receiver) = tokio::sync::mpsc::channel(1000);
-
-    let write_task = tokio::spawn(async move {
-        let mut protocol_buf = Vec::new();
-        let mut drop_redundant = false;
-        let mut in_bundle = false;
-
-        while let Some(event) = receiver.recv().await {
-            match event {
-                PacketSending::Packet(packet, bypass) => {
-                    if drop_redundant && !bypass {
-                        break;
-                    }
-                    let res = encode_and_send_packet(
-                        &mut write,
-                        &packet,
-                        &mut protocol_buf,
-                        compression_threshold,
-                        &mut encryption,
-                    )
-                    .await;
-                    if let Err(_e) = res {
-                        // TODO: Handle error
-                        break;
-                    }
-                }
-                PacketSending::Sync(sender) => {
-                    let _ = sender.send(());
-                }
-                PacketSending::DropRedundant(drop) => {
-                    drop_redundant = drop;
-                }
-                PacketSending::BundleReceived => {
-                    in_bundle = !in_bundle;
-                    if let Err(_e) = encode_and_send_packet(
-                        &mut write,
-                        &[0],
-                        &mut protocol_buf,
-                        compression_threshold,
-                        &mut encryption,
-                    )
-                    .await
-                    {
-                        break;
-                    }
-                }
-                PacketSending::StartConfig(version) => {
-                    if in_bundle {
-                        in_bundle = !in_bundle;
-                        if let Err(_e) = encode_and_send_packet(
-                            &mut write,
-                            &[0],
-                            &mut protocol_buf,
-                            compression_threshold,
-                            &mut encryption,
-                        )
-                        .await
-                        {
-                            break;
-                        }
-                    }
-                    if let Some(packet_id) = PacketRegistry::instance().get_server_packet_id(
-                        ProtocolState::Game,
-                        version,
-                        ServerPacketType::ClientboundStartConfigurationPacket,
-                    ) {
-                        if let Err(_e) = encode_and_send_packet(
-                            &mut write,
-                            &{
-                                let mut packet = vec![];
-                                VarInt(packet_id).encode(&mut packet, 5).unwrap();
-                                packet
-                            },
-                            &mut protocol_buf,
-                            compression_threshold,
-                            &mut encryption,
-                        )
-                        .await
-                        {
-                            break;
-                        }
-                    }
-                }
-
-                PacketSending::StartGame(version) => {
-                    if in_bundle {
-                        unreachable!("cant be in bundle while in config state")
-                    }
-                    if let Some(packet_id) = PacketRegistry::instance().get_server_packet_id(
-                        ProtocolState::Config,
-                        version,
-                        ServerPacketType::ClientboundFinishConfigurationPacket,
-                    ) {
-                        if let Err(_e) = encode_and_send_packet(
-                            &mut write,
-                            &{
-                                let mut packet = vec![];
-                                VarInt(packet_id).encode(&mut packet, 5).unwrap();
-                                packet
-                            },
-                            &mut protocol_buf,
-                            compression_threshold,
-                            &mut encryption,
-                        )
-                        .await
-                        {
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-    });
+    let (sender, send_queue, write_task) =
+        spawn_write_task(write, compression_threshold, encryption);
 
     let player_sync_data = PlayerSyncData {
         is_switching_server: Mutex::new(false),
@@ -205,6 +121,14 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
         config_ack_notify: Notify::new(),
         client_settings: Mutex::new(None),
         brand_packet: Mutex::new(None),
+        resource_packs: Mutex::new(ResourcePackTracker::default()),
+        tab_list: Mutex::new(TabListTracker::default()),
+        ping: AtomicI32::new(0),
+        pending_keep_alive: Mutex::new(None),
+        entity_id: AtomicI32::new(0),
+        boss_bars: Mutex::new(HashMap::new()),
+        in_limbo: AtomicBool::new(false),
+        limbo_capture: Mutex::new(LimboCapture::default()),
     };
     let handle = ConnectionHandle::new(
         display_name.clone(),
@@ -215,6 +139,7 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
         compression_threshold,
         decryption,
         data.address,
+        send_queue,
     );
     let disconnect_lock = handle.disconnect_wait.clone();
 
@@ -227,6 +152,8 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
         protocol_version: data.version,
         server_handle: None,
         player_public_key: data.player_public_key,
+        virtual_host: data.virtual_host.clone(),
+        local_address: data.local_address,
         sync_data: player_sync_data,
     });
 
@@ -259,20 +186,27 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
 
     debug!("{} Connecting to priority servers...", display_name);
     let server_data = 'l: {
-        let servers = ProxyServer::instance().servers().read().await;
-        for server in servers.get_priorities() {
-            let server_id = servers.get_server_by_name(server);
+        let priorities = match ProxyServer::instance().config().forced_hosts.get(&data.virtual_host) {
+            Some(host) if !host.priorities.is_empty() => host.priorities.clone(),
+            _ => ProxyServer::instance().servers().read().await.get_priorities().to_vec(),
+        };
+        for server in &priorities {
+            let server_id = server_group::resolve(server).await;
             if server_id.is_none() {
                 warn!("{} Skipping, prioritized server not found!", display_name);
                 continue;
             }
             let default_server = server_id.unwrap();
-            let addr = default_server.address.clone();
             let label = default_server.label.clone();
+            if !health::is_healthy(&label) {
+                debug!("{} Skipping {}, the server is offline", display_name, label);
+                continue;
+            }
 
             let backend = super::backend::connect(
                 data_address,
-                addr,
+                data.local_address,
+                &default_server,
                 "127.0.0.1".to_string(),
                 25565,
                 data_login_result.clone(),
@@ -285,7 +219,7 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
                 continue;
             }
 
-            break 'l Some((server.to_owned(), label, backend.unwrap()));
+            break 'l Some((label.clone(), label, backend.unwrap()));
         }
         None
     };
@@ -312,12 +246,17 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
         if let Some(ref backend_handle) = player_handle_clone.server_handle {
             backend_handle.disconnect("client disconnected").await;
         }
+        tab_list::request_update();
         
         drop(disconnect_guard);
     });
 
     if server_data.is_none() {
-        player.kick(Text::new("§cNo server found for you to connect")).await.ok();
+        if limbo::is_enabled() {
+            limbo::enter(player);
+        } else {
+            player.kick(Text::new("§cNo server found for you to connect")).await.ok();
+        }
         return;
     }
 
@@ -327,6 +266,13 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
     player.current_server = Some(label);
     player.server_handle = Some(backend_handle.clone());
 
+    if let Err(e) = resource_pack::send_server_packs(&player, &server_name).await {
+        warn!("{} Failed to send resource packs: {}", display_name, e);
+    }
+    if let Err(e) = server_links::send_server_links(&player, &server_name).await {
+        warn!("{} Failed to send server links: {}", display_name, e);
+    }
+
     con_handle
         .spawn_read_task(
             true,
@@ -340,81 +286,264 @@ pub async fn handle(mut stream: TcpStream, data: ProxyingData) {
 
 async fn read_task(
     packet_limit: bool,
-    _display_name: String,
+    display_name: String,
     partner: ConnectionHandle,
     self_handle: ConnectionHandle,
     player: WeakHandle<ProxiedPlayer>,
     version: i32,
 ) {
-    let mut read_buf = Vec::new();
-    let mut protocol_buf = Vec::new();
+    let mut pool = BufferPool::default();
     let mut read = self_handle.reader.lock().await;
     let mut decryption = self_handle.decryption.lock().await;
-    let mut packet_per_second = 0usize;
-    let mut last_second = SystemTime::now();
+    let mut limiter = packet_limit.then(PacketLimiter::new);
     let mut should_forward = true;
+    let compression_threshold = self_handle.compression_threshold;
     loop {
-        let res = read_and_decode_packet(
+        let state = self_handle.protocol_state();
+        let res = read_frame(
             read.deref_mut(),
-            &mut read_buf,
-            &mut protocol_buf,
-            self_handle.compression_threshold,
+            pool.get(),
             decryption.deref_mut(),
+            limits::limits().max_frame_size(state),
         )
         .await;
         if let Err(e) = res {
+            limits::log_violation(&display_name, state, version, None, &e);
             partner.disconnect(&e.to_string()).await;
             self_handle.disconnect(&e.to_string()).await;
             break;
         }
-
-        if packet_limit {
-            packet_per_second += 1;
-            if packet_per_second >= 2000 {
-                if let Ok(elapsed) = last_second.elapsed() {
-                    if elapsed.as_millis() < 1000 {
-                        self_handle.disconnect("to many packets").await;
-                        partner.disconnect("to many packets").await;
+        let frame = pool.split();
+
+        let packet_id = if compression_threshold != -1 {
+            compression::peek_packet_id(&frame)
+        } else {
+            VarInt::decode_simple(&mut Cursor::new(&frame)).map(|packet_id| packet_id.get())
+        };
+        let packet_id = match packet_id {
+            Ok(packet_id) => packet_id,
+            Err(e) => {
+                limits::log_violation(&display_name, state, version, None, &e);
+                partner.disconnect(&e.to_string()).await;
+                self_handle.disconnect(&e.to_string()).await;
+                break;
+            }
+        };
+
+        if let Some(ref mut limiter) = limiter {
+            // the size the client declares for a compressed packet can't be trusted, the frame is what it sent
+            match limiter.check(&display_name, state, version, packet_id, frame.len()) {
+                Verdict::Allow => {}
+                Verdict::Drop => {
+                    if let Err(e) = acknowledge_dropped(
+                        &frame,
+                        compression_threshold,
+                        state,
+                        version,
+                        packet_id,
+                        &player,
+                    )
+                    .await
+                    {
+                        limits::log_violation(&display_name, state, version, Some(packet_id), &e);
+                        partner.disconnect(&e.to_string()).await;
+                        self_handle.disconnect(&e.to_string()).await;
                         break;
                     }
-                    last_second = SystemTime::now();
-                    packet_per_second = 0;
+                    continue;
+                }
+                Verdict::Kick(reason) => {
+                    self_handle.disconnect(&reason).await;
+                    partner.disconnect(&reason).await;
+                    break;
                 }
             }
         }
 
-        let packet_id = VarInt::decode_simple(&mut Cursor::new(&read_buf));
-        if let Err(e) = packet_id {
-            self_handle.disconnect(&e.to_string()).await;
-            break;
+        // packets the handler does not inspect are forwarded without decompressing them
+        if compression_threshold == partner.compression_threshold
+            && PacketRegistry::instance()
+                .get_client_packet_type(state, version, packet_id)
+                .is_none()
+        {
+            if should_forward {
+                if let Err(e) = partner.queue_frame(frame).await {
+                    partner.disconnect(&e.to_string()).await;
+                    should_forward = false;
+                }
+            }
+            continue;
         }
-        let packet_id = packet_id.unwrap().get();
+
+        let packet = if compression_threshold != -1 {
+            if let Err(e) = compression::decompress(&frame, &mut pool.get().writer()) {
+                limits::log_violation(&display_name, state, version, Some(packet_id), &e);
+                partner.disconnect(&e.to_string()).await;
+                self_handle.disconnect(&e.to_string()).await;
+                break;
+            }
+            pool.split()
+        } else {
+            frame
+        };
+
         let res = ClientPacketHandler::handle_packet(
             packet_id,
-            &read_buf[VarInt::get_size(packet_id)..],
+            &packet[VarInt::get_size(packet_id)..],
             version,
             &player,
             &self_handle,
         )
         .await;
         if let Err(e) = res {
+            limits::log_violation(&display_name, state, version, Some(packet_id), &e);
             partner.disconnect(&e.to_string()).await;
             self_handle.disconnect(&e.to_string()).await;
             break;
         }
         if should_forward && res.unwrap() {
-            if let Err(e) = partner.queue_packet(read_buf.clone(), false).await {
+            if let Err(e) = partner.queue_packet(packet, false).await {
                 partner.disconnect(&e.to_string()).await;
                 should_forward = false;
             }
         }
-        read_buf.clear();
-       // read_buf = Vec::new();
     }
 }
 
+/// A dropped signed command still acknowledged the chat messages before it, the backend has to
+/// know about them or it rejects the next signed message
+async fn acknowledge_dropped(
+    frame: &[u8],
+    compression_threshold: i32,
+    state: ProtocolState,
+    version: i32,
+    packet_id: i32,
+    player: &WeakHandle<ProxiedPlayer>,
+) -> IOResult<()> {
+    if PacketRegistry::instance().get_client_packet_type(state, version, packet_id)
+        != Some(ClientPacketType::SignedClientCommand)
+    {
+        return Ok(());
+    }
+    let mut packet = Vec::new();
+    if compression_threshold != -1 {
+        compression::decompress(frame, &mut packet)?;
+    } else {
+        packet.extend_from_slice(frame);
+    }
+    let command = SignedClientCommand::decode(
+        &mut Cursor::new(&packet[VarInt::get_size(packet_id)..]),
+        version,
+    )?;
+    if let Some(player) = player.upgrade() {
+        chat_pipeline::acknowledge_messages(&player, command.offset).await?;
+    }
+    Ok(())
+}
+
+/// A packet without fields
+fn id_only_packet(packet_id: i32) -> Vec<u8> {
+    let mut packet = vec![];
+    VarInt(packet_id).encode(&mut packet, 5).unwrap();
+    packet
+}
+
+/// Starts the task that writes the queued packets to the client in batches
+pub(crate) fn spawn_write_task(
+    write: WriteHalf,
+    compression_threshold: i32,
+    encryption: Option<PacketEncryption>,
+) -> (Sender<PacketSending>, Arc<SendQueue>, JoinHandle<()>) {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(1000);
+    let send_queue = Arc::new(SendQueue::default());
+    let taken = send_queue.clone();
+
+    let write_task = tokio::spawn(async move {
+        let mut writer = BatchWriter::new(write, compression_threshold, encryption);
+        let mut drop_redundant = false;
+        let mut in_bundle = false;
+
+        'batches: while let Some(event) = receiver.recv().await {
+            let deadline = writer.deadline();
+            let mut next = Some(event);
+            while let Some(event) = next {
+                let res = match event {
+                    PacketSending::Packet(packet, bypass) => {
+                        taken.release(packet.len());
+                        if drop_redundant && !bypass {
+                            break 'batches;
+                        }
+                        writer.push_packet(&packet)
+                    }
+                    PacketSending::Frame(frame) => {
+                        taken.release(frame.len());
+                        if drop_redundant {
+                            break 'batches;
+                        }
+                        writer.push_frame(&frame)
+                    }
+                    PacketSending::Sync(sender) => {
+                        let res = writer.flush().await;
+                        let _ = sender.send(());
+                        res
+                    }
+                    PacketSending::DropRedundant(drop) => {
+                        drop_redundant = drop;
+                        Ok(())
+                    }
+                    PacketSending::BundleReceived => {
+                        in_bundle = !in_bundle;
+                        writer.push_packet(&[0])
+                    }
+                    PacketSending::StartConfig(version) => {
+                        let mut res = Ok(());
+                        if in_bundle {
+                            in_bundle = !in_bundle;
+                            res = writer.push_packet(&[0]);
+                        }
+                        if let Some(packet_id) = PacketRegistry::instance().get_server_packet_id(
+                            ProtocolState::Game,
+                            version,
+                            ServerPacketType::ClientboundStartConfigurationPacket,
+                        ) {
+                            res = res.and_then(|_| writer.push_packet(&id_only_packet(packet_id)));
+                        }
+                        res
+                    }
+                    PacketSending::StartGame(version) => {
+                        if in_bundle {
+                            unreachable!("cant be in bundle while in config state")
+                        }
+                        match PacketRegistry::instance().get_server_packet_id(
+                            ProtocolState::Config,
+                            version,
+                            ServerPacketType::ClientboundFinishConfigurationPacket,
+                        ) {
+                            Some(packet_id) => writer.push_packet(&id_only_packet(packet_id)),
+                            None => Ok(()),
+                        }
+                    }
+                };
+                if let Err(_e) = res {
+                    // TODO: Handle error
+                    break 'batches;
+                }
+                next = writer.next_event(&mut receiver, deadline).await;
+            }
+            if let Err(_e) = writer.flush().await {
+                break;
+            }
+        }
+        let _ = writer.flush().await;
+        taken.wake();
+    });
+    (sender, send_queue, write_task)
+}
+
 pub(crate) enum PacketSending {
-    Packet(Vec<u8>, bool),
+    Packet(Bytes, bool),
+    /// a frame that is already compressed with the threshold of the connection
+    Frame(Bytes),
     Sync(tokio::sync::oneshot::Sender<()>),
     DropRedundant(bool),
     BundleReceived,
@@ -428,13 +557,16 @@ pub struct ConnectionHandle {
     sender: Sender<PacketSending>,
     pub(crate) protocol_state: Arc<AtomicU8>,
     pub(crate) compression_threshold: i32,
-    pub(crate) reader: Arc<Mutex<OwnedReadHalf>>,
+    pub(crate) reader: Arc<Mutex<ReadHalf>>,
     pub(crate) decryption: Arc<Mutex<Option<PacketDecryption>>>,
     write_task: AbortHandle,
     pub(crate) read_task: Arc<Mutex<Option<AbortHandle>>>,
     pub(crate) disconnect_wait: Arc<RwLock<()>>,
     pub address: SocketAddr,
     pub(crate) closed: Arc<AtomicBool>,
+    pub(crate) send_queue: Arc<SendQueue>,
+    /// label of the backend server, none for client connections
+    pub server_name: Option<String>,
 }
 
 impl Display for ConnectionHandle {
@@ -454,12 +586,13 @@ impl ConnectionHandle {
     pub(crate) fn new(
         name: String,
         sender: Sender<PacketSending>,
-        reader: OwnedReadHalf,
+        reader: ReadHalf,
         protocol_state: ProtocolState,
         write_task: AbortHandle,
         compression_threshold: i32,
         decryption: Option<PacketDecryption>,
         address: SocketAddr,
+        send_queue: Arc<SendQueue>,
     ) -> Self {
         Self {
             name,
@@ -473,6 +606,8 @@ impl ConnectionHandle {
             disconnect_wait: Arc::new(RwLock::new(())),
             address,
             closed: Arc::new(AtomicBool::new(false)),
+            send_queue,
+            server_name: None,
         }
     }
 
@@ -507,69 +642,84 @@ impl ConnectionHandle {
         old_read_task.replace(read_task.abort_handle());
     }
 
+    /// Queues an event for the write task, a connection that does not take ``size`` more bytes in time is closed
+    async fn send(&self, event: PacketSending, size: usize, error: &str) -> IOResult<()> {
+        let config = &ProxyServer::instance().config().network;
+        let deadline = tokio::time::Instant::now() + config.send_timeout();
+        if !self.send_queue.reserve(size, deadline, &self.sender).await {
+            self.disconnect(TOO_SLOW).await;
+            return Err(IOError::new(std::io::ErrorKind::TimedOut, TOO_SLOW));
+        }
+        match tokio::time::timeout_at(deadline, self.sender.send(event)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => {
+                self.send_queue.release(size);
+                Err(IOError::new(std::io::ErrorKind::Other, error))
+            }
+            Err(_) => {
+                self.send_queue.release(size);
+                self.disconnect(TOO_SLOW).await;
+                Err(IOError::new(std::io::ErrorKind::TimedOut, TOO_SLOW))
+            }
+        }
+    }
+
     pub async fn sync(&self) -> IOResult<()> {
         let (sender, receiver) = tokio::sync::oneshot::channel();
-        self.sender
-            .send(PacketSending::Sync(sender))
-            .await
-            .map_err(|_| IOError::new(std::io::ErrorKind::Other, "Failed to queue sync packet!"))?;
+        self.send(PacketSending::Sync(sender), 0, "Failed to queue sync packet!")
+            .await?;
         receiver
             .await
             .map_err(|_| IOError::new(std::io::ErrorKind::Other, "Failed to receive sync packet!"))
     }
 
-    pub async fn queue_packet(&self, packet: Vec<u8>, bypass: bool) -> IOResult<()> {
-        self.sender
-            .send(PacketSending::Packet(packet, bypass))
+    pub async fn queue_packet<B: Into<Bytes>>(&self, packet: B, bypass: bool) -> IOResult<()> {
+        let packet = packet.into();
+        let size = packet.len();
+        self.send(PacketSending::Packet(packet, bypass), size, "Failed to queue packet!")
             .await
-            .map_err(|_| IOError::new(std::io::ErrorKind::Other, "Failed to queue packet!"))
     }
 
-    pub async fn drop_redundant(&self, drop: bool) -> IOResult<()> {
-        self.sender
-            .send(PacketSending::DropRedundant(drop))
+    /// Queues a frame as it was read from the partner, both connections must use the same compression threshold
+    pub async fn queue_frame(&self, frame: Bytes) -> IOResult<()> {
+        let size = frame.len();
+        self.send(PacketSending::Frame(frame), size, "Failed to queue packet!")
             .await
-            .map_err(|_| {
-                IOError::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to queue drop redundant packet!",
-                )
-            })
+    }
+
+    pub async fn drop_redundant(&self, drop: bool) -> IOResult<()> {
+        self.send(
+            PacketSending::DropRedundant(drop),
+            0,
+            "Failed to queue drop redundant packet!",
+        )
+        .await
     }
 
     pub async fn on_bundle(&self) -> IOResult<()> {
-        self.sender
-            .send(PacketSending::BundleReceived)
-            .await
-            .map_err(|_| {
-                IOError::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to queue bundle received packet!",
-                )
-            })
+        self.send(
+            PacketSending::BundleReceived,
+            0,
+            "Failed to queue bundle received packet!",
+        )
+        .await
     }
 
     pub async fn goto_config(&self, version: i32) -> IOResult<()> {
-        self.sender
-            .send(PacketSending::StartConfig(version))
-            .await
-            .map_err(|_| {
-                IOError::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to queue start config packet!",
-                )
-            })
+        self.send(
+            PacketSending::StartConfig(version),
+            0,
+            "Failed to queue start config packet!",
+        )
+        .await
     }
     pub async fn goto_game(&self, version: i32) -> IOResult<()> {
-        self.sender
-            .send(PacketSending::StartGame(version))
-            .await
-            .map_err(|_| {
-                IOError::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to queue start config packet!",
-                )
-            })
+        self.send(
+            PacketSending::StartGame(version),
+            0,
+            "Failed to queue start config packet!",
+        )
+        .await
     }
 
     pub async fn disconnect(&self, reason: &str) {
@@ -580,6 +730,7 @@ impl ConnectionHandle {
         self.closed.swap(true, Ordering::Relaxed);
         info!("{} disconnected: {}", self.name, reason);
         self.write_task.abort();
+        self.send_queue.wake();
         if let Some(task) = self.read_task.lock().await.take() {
             task.abort();
         }
@@ -0,0 +1,290 @@
+use std::{collections::HashMap, sync::atomic::Ordering, time::Duration};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    chat::Text,
+    util::{Handle, WeakHandle},
+};
+
+use super::{backend::ConnectError, circuit_breaker, health, ProxiedPlayer, ProxyServer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueDisplay {
+    ActionBar,
+    /// only sent when the position changes
+    Chat,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Players are queued if a server kicks them during the login with a reason containing one of these
+    #[serde(default = "default_kick_reasons")]
+    pub kick_reasons: Vec<String>,
+    /// One permission per tier, players with the first one are in front of everyone else
+    #[serde(default)]
+    pub priority_permissions: Vec<String>,
+    #[serde(default = "default_display")]
+    pub display: QueueDisplay,
+    /// Supports ``{server}``, ``{position}`` and ``{size}``
+    #[serde(default = "default_message")]
+    pub message: String,
+    /// Milliseconds between attempts to move players into servers with free slots
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_kick_reasons() -> Vec<String> {
+    vec!["full".to_owned(), "starting".to_owned()]
+}
+
+fn default_display() -> QueueDisplay {
+    QueueDisplay::ActionBar
+}
+
+fn default_message() -> String {
+    "&eYou are in the queue for &6{server}&e: &6{position}&e/&6{size}".to_owned()
+}
+
+fn default_interval() -> u64 {
+    2000
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            kick_reasons: default_kick_reasons(),
+            priority_permissions: Vec::new(),
+            display: default_display(),
+            message: default_message(),
+            interval: default_interval(),
+        }
+    }
+}
+
+struct QueueEntry {
+    player: WeakHandle<ProxiedPlayer>,
+    uuid: Uuid,
+    tier: usize,
+    last_position: usize,
+    /// a connection attempt is running
+    moving: bool,
+}
+
+lazy_static! {
+    static ref QUEUES: Mutex<HashMap<String, Vec<QueueEntry>>> = Mutex::new(HashMap::new());
+}
+
+pub fn is_enabled() -> bool {
+    ProxyServer::instance().config().queue.enabled
+}
+
+/// Whether a failed connection means the server is full or starting and the player should wait for it
+pub fn should_queue(error: &ConnectError) -> bool {
+    let config = &ProxyServer::instance().config().queue;
+    if !config.enabled {
+        return false;
+    }
+    match error {
+        ConnectError::Kicked(reason) => {
+            let reason = reason.to_lowercase();
+            config
+                .kick_reasons
+                .iter()
+                .any(|kick_reason| reason.contains(&kick_reason.to_lowercase()))
+        }
+        _ => false,
+    }
+}
+
+fn tier(player: &ProxiedPlayer) -> usize {
+    let permissions = &ProxyServer::instance().config().queue.priority_permissions;
+    permissions
+        .iter()
+        .position(|permission| player.has_permission(permission))
+        .unwrap_or(permissions.len())
+}
+
+/// Puts the player behind everyone of the same or a higher tier, a player waits in one queue at a time
+pub async fn enqueue(player: &Handle<ProxiedPlayer>, server: &str) {
+    let mut queues = QUEUES.lock().await;
+    for (name, queue) in queues.iter_mut() {
+        if name != server {
+            queue.retain(|entry| entry.uuid != player.uuid);
+        }
+    }
+    let queue = queues.entry(server.to_owned()).or_default();
+    if queue.iter().any(|entry| entry.uuid == player.uuid) {
+        return;
+    }
+    let tier = tier(player);
+    let index = queue
+        .iter()
+        .position(|entry| entry.tier > tier)
+        .unwrap_or(queue.len());
+    queue.insert(
+        index,
+        QueueEntry {
+            player: player.downgrade(),
+            uuid: player.uuid,
+            tier,
+            last_position: index + 1,
+            moving: false,
+        },
+    );
+    let size = queue.len();
+    drop(queues);
+    show_position(player, server, index + 1, size).await;
+}
+
+/// Removes the player from the queue they are waiting in and returns the server of that queue
+pub async fn leave(uuid: &Uuid) -> Option<String> {
+    let mut queues = QUEUES.lock().await;
+    for (server, queue) in queues.iter_mut() {
+        if let Some(index) = queue.iter().position(|entry| entry.uuid == *uuid) {
+            queue.remove(index);
+            return Some(server.clone());
+        }
+    }
+    None
+}
+
+pub fn start() {
+    if !is_enabled() {
+        return;
+    }
+    let interval = Duration::from_millis(ProxyServer::instance().config().queue.interval.max(100));
+    ProxyServer::instance().spawn_task(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            advance().await;
+        }
+    });
+}
+
+async fn show_position(player: &ProxiedPlayer, server: &str, position: usize, size: usize) {
+    let config = &ProxyServer::instance().config().queue;
+    let text = Text::new(
+        config
+            .message
+            .replace('&', "§")
+            .replace("{server}", server)
+            .replace("{position}", &position.to_string())
+            .replace("{size}", &size.to_string()),
+    );
+    // packets must not be queued while the player is switching servers
+    let switch_lock = player.sync_data.is_switching_server.lock().await;
+    if *switch_lock {
+        return;
+    }
+    match config.display {
+        QueueDisplay::ActionBar => player.send_action_bar(text).await.ok(),
+        QueueDisplay::Chat => player.send_message(text).await.ok(),
+    };
+    drop(switch_lock);
+}
+
+/// Free slots of the server as known from the health checks, one if nothing is known
+fn free_slots(server: &str) -> usize {
+    match health::status(server) {
+        Some(status) if status.max_players > 0 => {
+            (status.max_players - status.players).max(0) as usize
+        }
+        _ => 1,
+    }
+}
+
+/// Puts the player behind everyone of the same tier after a failed attempt, so a player the
+/// server keeps rejecting does not block the queue
+async fn rotate(uuid: &Uuid, server: &str) {
+    let mut queues = QUEUES.lock().await;
+    let queue = match queues.get_mut(server) {
+        Some(queue) => queue,
+        None => return,
+    };
+    if let Some(index) = queue.iter().position(|entry| entry.uuid == *uuid) {
+        let mut entry = queue.remove(index);
+        entry.moving = false;
+        let index = queue
+            .iter()
+            .position(|other| other.tier > entry.tier)
+            .unwrap_or(queue.len());
+        queue.insert(index, entry);
+    }
+}
+
+/// Moves as many players into every available server as it has free slots and tells the others their position
+async fn advance() {
+    let display = ProxyServer::instance().config().queue.display;
+    let mut updates = Vec::new();
+    let mut moves = Vec::new();
+    {
+        let mut queues = QUEUES.lock().await;
+        let servers = ProxyServer::instance().servers().read().await;
+        queues.retain(|server, queue| {
+            queue.retain(|entry| match entry.player.upgrade() {
+                Some(player) => {
+                    !player.client_handle.closed.load(Ordering::Relaxed)
+                        && player.current_server.as_deref() != Some(server.as_str())
+                }
+                None => false,
+            });
+            !queue.is_empty() && servers.get_server_by_name(server).is_some()
+        });
+        for (server, queue) in queues.iter_mut() {
+            let size = queue.len();
+            let mut slots = if health::is_healthy(server)
+                && !circuit_breaker::is_open(server)
+                && servers
+                    .get_server_by_name(server)
+                    .is_some_and(|info| info.available)
+            {
+                // players that are already connecting take a slot too
+                free_slots(server).saturating_sub(queue.iter().filter(|entry| entry.moving).count())
+            } else {
+                0
+            };
+            for (index, entry) in queue.iter_mut().enumerate() {
+                let player = match entry.player.upgrade() {
+                    Some(player) => player,
+                    None => continue,
+                };
+                if slots > 0 && !entry.moving {
+                    slots -= 1;
+                    entry.moving = true;
+                    moves.push((player.clone(), server.clone()));
+                }
+                if display == QueueDisplay::ActionBar || entry.last_position != index + 1 {
+                    updates.push((player, server.clone(), index + 1, size));
+                }
+                entry.last_position = index + 1;
+            }
+        }
+    }
+    for (player, server, position, size) in updates {
+        show_position(&player, &server, position, size).await;
+    }
+    for (player, server) in moves {
+        tokio::spawn(async move {
+            if let Some(switch) = ProxiedPlayer::switch_server(player.clone(), server.clone()).await
+            {
+                if let Ok(true) = switch.await {
+                    leave(&player.uuid).await;
+                    return;
+                }
+            }
+            rotate(&player.uuid, &server).await;
+        });
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Buckets that were refilled completely are forgotten after this time
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+/// New keys over this limit are let through without a bucket until the next cleanup, a flood of addresses
+/// can not exhaust the memory or lock out everyone else
+const MAX_BUCKETS: usize = 65536;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Requests that can be made at once
+    pub capacity: u32,
+    /// Requests per second that become available again
+    pub refill_rate: f64,
+    /// Addresses in the same subnet share a bucket, ``new`` uses single IPv4 addresses and IPv6 /64 networks
+    pub ipv4_prefix: u8,
+    pub ipv6_prefix: u8,
+}
+
+impl RateLimitConfig {
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        Self {
+            enabled: true,
+            capacity,
+            refill_rate,
+            ipv4_prefix: 32,
+            ipv6_prefix: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_rate,
+            tokens: capacity as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_n(1.0)
+    }
+
+    pub fn try_take_n(&mut self, tokens: f64) -> bool {
+        self.refill();
+        if self.tokens >= tokens {
+            self.tokens -= tokens;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+}
+
+/// Counts events in a window that slides over the last two fixed windows, weighting the older one by its overlap
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    window: Duration,
+    start: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl SlidingWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            start: Instant::now(),
+            current: 0,
+            previous: 0,
+        }
+    }
+
+    /// Adds the amount and returns the estimated total of the last window
+    pub fn add(&mut self, amount: u64) -> u64 {
+        let elapsed = self.start.elapsed();
+        if elapsed >= self.window * 2 {
+            self.previous = 0;
+            self.current = 0;
+            self.start = Instant::now();
+        } else if elapsed >= self.window {
+            self.previous = self.current;
+            self.current = 0;
+            self.start += self.window;
+        }
+        self.current += amount;
+        let overlap = 1.0 - self.start.elapsed().as_secs_f64() / self.window.as_secs_f64();
+        self.current + (self.previous as f64 * overlap.max(0.0)) as u64
+    }
+}
+
+/// One token bucket per key, buckets are created full
+pub struct RateLimiter<K> {
+    capacity: u32,
+    refill_rate: f64,
+    buckets: Mutex<(HashMap<K, TokenBucket>, Instant)>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        Self {
+            capacity,
+            refill_rate,
+            buckets: Mutex::new((HashMap::new(), Instant::now())),
+        }
+    }
+
+    /// Takes a token from the bucket of the key, false if it is empty
+    pub fn check(&self, key: K) -> bool {
+        let mut guard = self.buckets.lock().unwrap();
+        let (buckets, last_cleanup) = &mut *guard;
+        let full = buckets.len() >= MAX_BUCKETS;
+        if last_cleanup.elapsed() >= CLEANUP_INTERVAL
+            || (full && last_cleanup.elapsed() >= Duration::from_secs(1))
+        {
+            buckets.retain(|_, bucket| !bucket.is_full());
+            *last_cleanup = Instant::now();
+        }
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            // a new key would get a full bucket anyway
+            return true;
+        }
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(self.capacity, self.refill_rate))
+            .try_take()
+    }
+}
+
+/// Rate limiter keyed by the subnet of an address
+pub struct IpRateLimiter {
+    config: RateLimitConfig,
+    limiter: RateLimiter<IpAddr>,
+}
+
+impl IpRateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            limiter: RateLimiter::new(config.capacity, config.refill_rate),
+        }
+    }
+
+    pub fn check(&self, ip: IpAddr) -> bool {
+        !self.config.enabled
+            || self
+                .limiter
+                .check(subnet(ip, self.config.ipv4_prefix, self.config.ipv6_prefix))
+    }
+}
+
+/// Parses an address or a subnet like ``10.0.0.0/8`` into the masked address and the prefix length
+pub fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match cidr.split_once('/') {
+        Some((ip, prefix)) => (ip.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+        None => (cidr.parse::<IpAddr>().ok()?, None),
+    };
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max_prefix);
+    if prefix > max_prefix {
+        return None;
+    }
+    Some((subnet(ip, prefix, prefix), prefix))
+}
+
+/// The address with all bits after the prefix cleared
+pub fn subnet(ip: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX
+                .checked_shl(32 - ipv4_prefix.min(32) as u32)
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX
+                .checked_shl(128 - ipv6_prefix.min(128) as u32)
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn token_bucket_takes_until_empty() {
+        let mut bucket = TokenBucket::new(2, 0.0);
+        assert!(bucket.is_full());
+        assert!(bucket.try_take());
+        assert!(!bucket.is_full());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+        assert!(!bucket.try_take_n(0.5));
+    }
+
+    #[test]
+    fn token_bucket_refills_up_to_capacity() {
+        let mut bucket = TokenBucket::new(2, 1000.0);
+        assert!(bucket.try_take_n(2.0));
+        thread::sleep(Duration::from_millis(20));
+        assert!(bucket.is_full());
+        assert!(!bucket.try_take_n(3.0));
+    }
+
+    #[test]
+    fn sliding_window_counts_and_resets() {
+        let mut window = SlidingWindow::new(Duration::from_secs(3600));
+        assert_eq!(window.add(1), 1);
+        assert_eq!(window.add(5), 6);
+
+        let mut window = SlidingWindow::new(Duration::from_millis(5));
+        window.add(100);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(window.add(1), 1);
+    }
+
+    #[test]
+    fn rate_limiter_has_a_bucket_per_key() {
+        let limiter = RateLimiter::new(1, 0.0);
+        assert!(limiter.check(1));
+        assert!(!limiter.check(1));
+        assert!(limiter.check(2));
+    }
+
+    #[test]
+    fn rate_limiter_lets_new_keys_through_when_full() {
+        let limiter = RateLimiter::new(1, 0.0);
+        for key in 0..MAX_BUCKETS {
+            assert!(limiter.check(key));
+        }
+        assert!(!limiter.check(0));
+        assert!(limiter.check(MAX_BUCKETS));
+        assert!(limiter.check(MAX_BUCKETS));
+    }
+
+    #[test]
+    fn subnet_masks_ipv4() {
+        let ip: IpAddr = "192.168.17.34".parse().unwrap();
+        assert_eq!(subnet(ip, 32, 128), ip);
+        assert_eq!(
+            subnet(ip, 24, 128),
+            "192.168.17.0".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(subnet(ip, 1, 128), "128.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(subnet(ip, 0, 128), "0.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(subnet(ip, 40, 128), ip);
+    }
+
+    #[test]
+    fn subnet_masks_ipv6() {
+        let ip: IpAddr = "2001:db8:1:2:3:4:5:6".parse().unwrap();
+        assert_eq!(subnet(ip, 32, 128), ip);
+        assert_eq!(
+            subnet(ip, 32, 64),
+            "2001:db8:1:2::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            subnet(ip, 32, 48),
+            "2001:db8:1::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(subnet(ip, 32, 0), "::".parse::<IpAddr>().unwrap());
+        assert_eq!(subnet(ip, 32, 200), ip);
+    }
+
+    #[test]
+    fn parse_cidr_masks_the_address() {
+        assert_eq!(
+            parse_cidr("10.1.2.3/8"),
+            Some(("10.0.0.0".parse().unwrap(), 8))
+        );
+        assert_eq!(
+            parse_cidr("10.1.2.3"),
+            Some(("10.1.2.3".parse().unwrap(), 32))
+        );
+        assert_eq!(
+            parse_cidr("0.0.0.0/0"),
+            Some(("0.0.0.0".parse().unwrap(), 0))
+        );
+        assert_eq!(parse_cidr("::1"), Some(("::1".parse().unwrap(), 128)));
+        assert_eq!(
+            parse_cidr("2001:db8::1/32"),
+            Some(("2001:db8::".parse().unwrap(), 32))
+        );
+    }
+
+    #[test]
+    fn parse_cidr_rejects_invalid_input() {
+        assert_eq!(parse_cidr("10.0.0.0/33"), None);
+        assert_eq!(parse_cidr("::/129"), None);
+        assert_eq!(parse_cidr("10.0.0.0/"), None);
+        assert_eq!(parse_cidr("10.0.0.0/a"), None);
+        assert_eq!(parse_cidr("localhost"), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    chat::Text,
+    util::{Handle, IOResult},
+    version::R1_20_3,
+};
+
+use super::{
+    packets::{self, AddResourcePack, RemoveResourcePack, ResourcePackResponse},
+    proxy_handler::ConnectionHandle,
+    ProxiedPlayer, ProxyServer,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourcePackConfig {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub url: String,
+    pub hash: String,
+    #[serde(default)]
+    pub forced: bool,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub kick_on_decline: bool,
+}
+
+impl ResourcePackConfig {
+    pub fn uuid(&self) -> Uuid {
+        self.id
+            .as_deref()
+            .and_then(|id| Uuid::try_parse(id).ok())
+            .unwrap_or_else(|| uuid_from_url(&self.url))
+    }
+}
+
+/// The same name based uuid the vanilla server derives for its own pack
+pub fn uuid_from_url(url: &str) -> Uuid {
+    uuid::Builder::from_md5_bytes(md5::compute(url.as_bytes()).into()).into_uuid()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourcePackStatus {
+    Pending,
+    Loaded,
+    Declined,
+    FailedDownload,
+    Accepted,
+    Downloaded,
+    InvalidUrl,
+    FailedReload,
+    Discarded,
+}
+
+impl ResourcePackStatus {
+    pub fn from_id(id: i32) -> Option<Self> {
+        Some(match id {
+            0 => Self::Loaded,
+            1 => Self::Declined,
+            2 => Self::FailedDownload,
+            3 => Self::Accepted,
+            4 => Self::Downloaded,
+            5 => Self::InvalidUrl,
+            6 => Self::FailedReload,
+            7 => Self::Discarded,
+            _ => return None,
+        })
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            Self::Pending => -1,
+            Self::Loaded => 0,
+            Self::Declined => 1,
+            Self::FailedDownload => 2,
+            Self::Accepted => 3,
+            Self::Downloaded => 4,
+            Self::InvalidUrl => 5,
+            Self::FailedReload => 6,
+            Self::Discarded => 7,
+        }
+    }
+
+    /// Whether the client has the pack or is about to have it
+    pub fn is_applied(&self) -> bool {
+        matches!(self, Self::Accepted | Self::Downloaded | Self::Loaded)
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self,
+            Self::Declined | Self::FailedDownload | Self::InvalidUrl | Self::Discarded
+        )
+    }
+}
+
+pub struct TrackedPack {
+    pub hash: String,
+    pub status: ResourcePackStatus,
+    pub proxy_owned: bool,
+    pub kick_on_decline: bool,
+}
+
+#[derive(Default)]
+pub struct ResourcePackTracker {
+    pub packs: HashMap<Uuid, TrackedPack>,
+    /// clients before 1.20.3 only know a single pack and don't tell us which one they answer for
+    pub last_sent: Option<Uuid>,
+}
+
+/// All packs the proxy wants the player to have on the given server, later entries override earlier ones
+pub async fn applicable_packs(player: &ProxiedPlayer, server: &str) -> Vec<ResourcePackConfig> {
+    let config = ProxyServer::instance().config();
+    let mut packs = config.resource_packs.clone();
+    if let Some(host) = config.forced_hosts.get(&player.virtual_host) {
+        packs.extend(host.resource_packs.iter().cloned());
+    }
+    if let Some(info) = ProxyServer::instance()
+        .servers()
+        .read()
+        .await
+        .get_server_by_name(server)
+    {
+        packs.extend(info.resource_packs.iter().cloned());
+    }
+    let mut seen = Vec::new();
+    packs.retain(|pack| {
+        let id = pack.uuid();
+        if seen.contains(&id) {
+            return false;
+        }
+        seen.push(id);
+        true
+    });
+    packs
+}
+
+/// Sends the proxy managed packs for `server` to a client that is in the config state.
+/// Packs the client already accepted are skipped, proxy packs of the previous server are removed.
+pub async fn send_server_packs(player: &Handle<ProxiedPlayer>, server: &str) -> IOResult<()> {
+    let mut packs = applicable_packs(player, server).await;
+    let version = player.protocol_version;
+    let state = player.client_handle.protocol_state();
+    if version < R1_20_3 && packs.len() > 1 {
+        // only one server pack is supported, the most specific one wins
+        packs.drain(..packs.len() - 1);
+    }
+
+    let mut tracker = player.sync_data.resource_packs.lock().await;
+    if version >= R1_20_3 {
+        let stale = tracker
+            .packs
+            .iter()
+            .filter(|(id, pack)| pack.proxy_owned && !packs.iter().any(|p| p.uuid() == **id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in stale {
+            tracker.packs.remove(&id);
+            if let Some(data) = packets::get_full_server_packet_buf(
+                &RemoveResourcePack { id: Some(id) },
+                version,
+                state,
+            )? {
+                player.client_handle.queue_packet(data, false).await?;
+            }
+        }
+    }
+
+    for pack in packs {
+        let id = pack.uuid();
+        if let Some(tracked) = tracker.packs.get(&id) {
+            if tracked.hash.eq_ignore_ascii_case(&pack.hash) && tracked.status.is_applied() {
+                continue;
+            }
+        }
+        let packet = AddResourcePack {
+            id: Some(id),
+            url: pack.url.clone(),
+            hash: pack.hash.clone(),
+            forced: pack.forced,
+            prompt: pack.prompt.as_ref().map(|prompt| Text::new(prompt.replace('&', "§"))),
+        };
+        if let Some(data) = packets::get_full_server_packet_buf(&packet, version, state)? {
+            player.client_handle.queue_packet(data, false).await?;
+        }
+        tracker.last_sent = Some(id);
+        tracker.packs.insert(
+            id,
+            TrackedPack {
+                hash: pack.hash,
+                status: ResourcePackStatus::Pending,
+                proxy_owned: true,
+                kick_on_decline: pack.kick_on_decline,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Called for packs sent by a backend, returns true if the packet was answered by the proxy
+/// because the client already has this pack and must not be forwarded.
+pub async fn handle_server_pack(
+    player: &ProxiedPlayer,
+    packet: &AddResourcePack,
+    server_handle: &ConnectionHandle,
+) -> IOResult<bool> {
+    let version = player.protocol_version;
+    let id = packet.id.unwrap_or_else(|| uuid_from_url(&packet.url));
+    let mut tracker = player.sync_data.resource_packs.lock().await;
+    if let Some(tracked) = tracker.packs.get(&id) {
+        if tracked.hash.eq_ignore_ascii_case(&packet.hash) && tracked.status == ResourcePackStatus::Loaded {
+            let mut responses = vec![ResourcePackStatus::Accepted];
+            if version >= R1_20_3 {
+                responses.push(ResourcePackStatus::Downloaded);
+            }
+            responses.push(ResourcePackStatus::Loaded);
+            for status in responses {
+                if let Some(data) = packets::get_full_client_packet_buf(
+                    &ResourcePackResponse {
+                        id: packet.id,
+                        result: status.id(),
+                    },
+                    version,
+                    server_handle.protocol_state(),
+                )? {
+                    server_handle.queue_packet(data, false).await?;
+                }
+            }
+            return Ok(true);
+        }
+    }
+    tracker.last_sent = Some(id);
+    tracker.packs.insert(
+        id,
+        TrackedPack {
+            hash: packet.hash.clone(),
+            status: ResourcePackStatus::Pending,
+            proxy_owned: false,
+            kick_on_decline: false,
+        },
+    );
+    Ok(false)
+}
+
+pub async fn handle_server_remove(player: &ProxiedPlayer, packet: &RemoveResourcePack) {
+    let mut tracker = player.sync_data.resource_packs.lock().await;
+    match packet.id {
+        Some(id) => {
+            tracker.packs.remove(&id);
+        }
+        None => tracker.packs.clear(),
+    }
+}
+
+/// Updates the tracked status, returns true if the response belongs to a backend pack and should be forwarded
+pub async fn handle_response(player: &Handle<ProxiedPlayer>, packet: &ResourcePackResponse) -> bool {
+    let status = match ResourcePackStatus::from_id(packet.result) {
+        Some(status) => status,
+        None => return true,
+    };
+    let mut tracker = player.sync_data.resource_packs.lock().await;
+    let id = match packet.id.or(tracker.last_sent) {
+        Some(id) => id,
+        None => return true,
+    };
+    let tracked = match tracker.packs.get_mut(&id) {
+        Some(tracked) => tracked,
+        None => return true,
+    };
+    tracked.status = status;
+    if !tracked.proxy_owned {
+        return true;
+    }
+    if status.is_rejected() && tracked.kick_on_decline {
+        let player = player.clone();
+        tokio::spawn(async move {
+            player
+                .kick(Text::new("§cYou have to accept the resource pack to play on this server"))
+                .await
+                .ok();
+        });
+    }
+    false
+}
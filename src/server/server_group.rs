@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::util::WeakHandle;
+
+use super::{circuit_breaker, health, ProxyServer, ServerInfo, ServerList};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalancingStrategy {
+    #[default]
+    LowestPlayers,
+    RoundRobin,
+    Random,
+    /// the first member below the soft cap, the emptiest one once all of them reached it
+    FillFirst,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerGroupConfig {
+    /// labels of the member servers
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub strategy: BalancingStrategy,
+    #[serde(default = "default_soft_cap")]
+    pub soft_cap: usize,
+}
+
+fn default_soft_cap() -> usize {
+    50
+}
+
+/// A name that stands for one of several servers, connecting to it picks a member
+pub struct ServerGroup {
+    pub name: String,
+    pub servers: Vec<String>,
+    pub strategy: BalancingStrategy,
+    pub soft_cap: usize,
+    next: AtomicUsize,
+}
+
+impl ServerGroup {
+    pub fn new(name: String, config: &ServerGroupConfig) -> Self {
+        Self {
+            name,
+            servers: config.servers.clone(),
+            strategy: config.strategy,
+            soft_cap: config.soft_cap,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Picks an available and healthy member whose circuit breaker is closed, if there is none the strategy decides between all of them
+    pub fn pick<'a>(
+        &self,
+        servers: &'a ServerList,
+        player_counts: &HashMap<String, usize>,
+    ) -> Option<&'a ServerInfo> {
+        let members = self
+            .servers
+            .iter()
+            .filter_map(|name| servers.get_server_by_name(name))
+            .collect::<Vec<_>>();
+        let available = members
+            .iter()
+            .copied()
+            .filter(|info| {
+                info.available
+                    && health::is_healthy(&info.label)
+                    && !circuit_breaker::is_open(&info.label)
+            })
+            .collect::<Vec<_>>();
+        let candidates = if available.is_empty() { members } else { available };
+        if candidates.is_empty() {
+            return None;
+        }
+        let count = |info: &ServerInfo| player_counts.get(&info.label).copied().unwrap_or(0);
+        match self.strategy {
+            BalancingStrategy::LowestPlayers => candidates.into_iter().min_by_key(|info| count(info)),
+            BalancingStrategy::RoundRobin => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+                Some(candidates[index])
+            }
+            BalancingStrategy::Random => {
+                Some(candidates[rand::thread_rng().gen_range(0..candidates.len())])
+            }
+            BalancingStrategy::FillFirst => candidates
+                .iter()
+                .copied()
+                .find(|info| count(info) < self.soft_cap)
+                .or_else(|| candidates.into_iter().min_by_key(|info| count(info))),
+        }
+    }
+}
+
+/// Players per server label
+pub async fn player_counts() -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for player in ProxyServer::instance()
+        .player_by_name
+        .read()
+        .await
+        .values()
+        .filter_map(WeakHandle::upgrade)
+    {
+        if let Some(ref server) = player.current_server {
+            *counts.entry(server.clone()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The server with this label or a member of the group with this name, servers win over groups
+pub async fn resolve(name: &str) -> Option<ServerInfo> {
+    let counts = player_counts().await;
+    let servers = ProxyServer::instance().servers().read().await;
+    if let Some(info) = servers.get_server_by_name(name) {
+        return Some(info.clone());
+    }
+    servers
+        .get_group(name)
+        .and_then(|group| group.pick(&servers, &counts))
+        .cloned()
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::Text,
+    util::{Handle, IOResult},
+    version::R1_21,
+};
+
+use super::{
+    packets::{self, CustomReportDetails, ServerLink, ServerLinkLabel, ServerLinks},
+    ProxiedPlayer, ProxyServer, FULL_NAME, NAME,
+};
+
+const BUILT_IN_LABELS: &[&str] = &[
+    "bug_report",
+    "community_guidelines",
+    "support",
+    "status",
+    "feedback",
+    "community",
+    "website",
+    "forums",
+    "news",
+    "announcements",
+];
+
+const MAX_REPORT_DETAILS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerLinkConfig {
+    /// one of the vanilla labels like `website` or `support`, anything else is shown as custom text
+    pub label: String,
+    pub url: String,
+}
+
+impl ServerLinkConfig {
+    pub fn to_link(&self) -> ServerLink {
+        let label = match BUILT_IN_LABELS.iter().position(|label| *label == self.label) {
+            Some(id) => ServerLinkLabel::BuiltIn(id as i32),
+            None => ServerLinkLabel::Custom(Text::new(self.label.replace('&', "§"))),
+        };
+        ServerLink {
+            label,
+            url: self.url.clone(),
+        }
+    }
+}
+
+/// The links of the server if it overrides them, the global ones otherwise
+pub async fn proxy_links(server: &str) -> Vec<ServerLink> {
+    let servers = ProxyServer::instance().servers().read().await;
+    let links = match servers
+        .get_server_by_name(server)
+        .and_then(|info| info.server_links.as_ref())
+    {
+        Some(links) => links,
+        None => &ProxyServer::instance().config().server_links,
+    };
+    links.iter().map(ServerLinkConfig::to_link).collect()
+}
+
+pub fn proxy_report_details(server: &str) -> Vec<(String, String)> {
+    vec![
+        ("Proxy".to_owned(), NAME.to_owned()),
+        ("Proxy version".to_owned(), FULL_NAME.to_owned()),
+        ("Backend server".to_owned(), server.to_owned()),
+    ]
+}
+
+/// Sends the proxy links and report details to a 1.21+ client, the backend may extend them later on
+pub async fn send_server_links(player: &Handle<ProxiedPlayer>, server: &str) -> IOResult<()> {
+    let version = player.protocol_version;
+    if version < R1_21 {
+        return Ok(());
+    }
+    let state = player.client_handle.protocol_state();
+    let details = CustomReportDetails {
+        details: proxy_report_details(server),
+    };
+    if let Some(data) = packets::get_full_server_packet_buf(&details, version, state)? {
+        player.client_handle.queue_packet(data, false).await?;
+    }
+    let links = proxy_links(server).await;
+    if !links.is_empty() {
+        if let Some(data) =
+            packets::get_full_server_packet_buf(&ServerLinks { links }, version, state)?
+        {
+            player.client_handle.queue_packet(data, false).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts the proxy links in front of the ones sent by the backend, links to the same url are only kept once
+pub async fn merge_links(packet: &mut ServerLinks, server: &str) {
+    let mut links = proxy_links(server).await;
+    for link in packet.links.drain(..) {
+        if !links.iter().any(|l| l.url == link.url) {
+            links.push(link);
+        }
+    }
+    packet.links = links;
+}
+
+pub fn merge_report_details(packet: &mut CustomReportDetails, server: &str) {
+    let mut details = proxy_report_details(server);
+    for (title, description) in packet.details.drain(..) {
+        if !details.iter().any(|(t, _)| *t == title) {
+            details.push((title, description));
+        }
+    }
+    details.truncate(MAX_REPORT_DETAILS);
+    packet.details = details;
+}
@@ -1,10 +1,206 @@
-use serde::Serialize;
-use std::fmt::Display;
+use lazy_static::lazy_static;
+use log::debug;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
 
-use super::ProxyServer;
+use crate::{
+    chat::{self, Text},
+    util::{IOError, IOErrorKind, IOResult, WeakHandle},
+    version::{self, ALL_VERSION_INDICES, ALL_VERSION_NAMES, SUPPORTED_VERSION_RANGE},
+};
 
-pub fn get_status_response(client_version: i32) -> StatusResponse {
+use super::{
+    health,
+    rate_limit::{IpRateLimiter, RateLimitConfig},
+    server_group, throttle, ProxyServer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusMode {
+    /// the player count and motd of the proxy
+    #[default]
+    Proxy,
+    /// the player counts of all backends from the health checks
+    Aggregate,
+    /// the response of the server the player would join
+    Passthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerSample {
+    /// random players connected to the proxy
+    #[default]
+    Players,
+    /// one line per online server with its player count
+    Servers,
+    None,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusConfig {
+    #[serde(default)]
+    pub mode: StatusMode,
+    /// The player sample shown when hovering the player count in aggregate mode
+    #[serde(default)]
+    pub sample: PlayerSample,
+    #[serde(default = "default_sample_size")]
+    pub sample_size: usize,
+    /// Use the sum of the backend slots instead of max_players in aggregate mode
+    #[serde(default)]
+    pub sum_max_players: bool,
+    /// Replace the favicon of the backend with the one of the proxy in passthrough mode
+    #[serde(default)]
+    pub proxy_favicon: bool,
+    /// Milliseconds until the proxy answers with its own status in passthrough mode
+    #[serde(default = "default_passthrough_timeout")]
+    pub passthrough_timeout: u64,
+    /// If not empty one of them is picked at random instead of motd, forced hosts can have their own
+    #[serde(default)]
+    pub motds: Vec<Value>,
+    /// Shown to clients with a version the proxy does not support
+    #[serde(default)]
+    pub unsupported_motd: Option<Value>,
+    /// Shown as max players instead of the real slots
+    #[serde(default)]
+    pub fake_max_players: Option<i32>,
+    /// Lines shown when hovering the player count instead of the player sample
+    #[serde(default)]
+    pub hover: Vec<String>,
+    /// Status requests per subnet (/24 and /48 by default), pings over the limit are closed without a response
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: RateLimitConfig,
+    /// Milliseconds a response is reused for the same version and host
+    #[serde(default = "default_cache_duration")]
+    pub cache_duration: u64,
+}
+
+fn default_sample_size() -> usize {
+    12
+}
+
+fn default_passthrough_timeout() -> u64 {
+    2000
+}
+
+fn default_rate_limit() -> RateLimitConfig {
+    RateLimitConfig {
+        ipv4_prefix: 24,
+        ipv6_prefix: 48,
+        ..RateLimitConfig::new(10, 2.0)
+    }
+}
+
+fn default_cache_duration() -> u64 {
+    1000
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            mode: StatusMode::default(),
+            sample: PlayerSample::default(),
+            sample_size: default_sample_size(),
+            sum_max_players: false,
+            proxy_favicon: false,
+            passthrough_timeout: default_passthrough_timeout(),
+            motds: Vec::new(),
+            unsupported_motd: None,
+            fake_max_players: None,
+            hover: Vec::new(),
+            rate_limit: default_rate_limit(),
+            cache_duration: default_cache_duration(),
+        }
+    }
+}
+
+/// Responses by protocol version and forced host with the time they were created
+type StatusCache = HashMap<(i32, String), (Instant, Arc<String>)>;
+
+lazy_static! {
+    static ref RATE_LIMITER: IpRateLimiter =
+        IpRateLimiter::new(&ProxyServer::instance().config().status.rate_limit);
+    static ref CACHE: Mutex<StatusCache> = Mutex::new(HashMap::new());
+}
+
+/// Takes a token from the status bucket of the address
+pub fn check_rate_limit(ip: IpAddr) -> bool {
+    throttle::is_exempt(ip) || RATE_LIMITER.check(ip)
+}
+
+/// The json of the status response for the configured mode, reused for a short time
+pub async fn status_response_json(client_version: i32, virtual_host: &str) -> Arc<String> {
+    let config = ProxyServer::instance().config();
+    // unknown versions and hosts get the same response, so they can not fill the cache
+    let version = if ALL_VERSION_INDICES.contains_key(&client_version) {
+        client_version
+    } else {
+        -1
+    };
+    let host = if config.forced_hosts.contains_key(virtual_host) {
+        virtual_host
+    } else {
+        ""
+    };
+    let key = (version, host.to_owned());
+    let cache_duration = Duration::from_millis(config.status.cache_duration);
+    if let Some((created, json)) = CACHE.lock().unwrap().get(&key) {
+        if created.elapsed() < cache_duration {
+            return json.clone();
+        }
+    }
+    let json = Arc::new(build_status_response_json(client_version, host).await);
+    if !cache_duration.is_zero() {
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), json.clone()));
+    }
+    json
+}
+
+async fn build_status_response_json(client_version: i32, virtual_host: &str) -> String {
+    match ProxyServer::instance().config().status.mode {
+        StatusMode::Proxy => get_status_response(client_version, virtual_host).to_string(),
+        StatusMode::Aggregate => get_aggregated_status_response(client_version, virtual_host)
+            .await
+            .to_string(),
+        StatusMode::Passthrough => match passthrough(client_version, virtual_host).await {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("Status passthrough failed: {}", e);
+                get_status_response(client_version, virtual_host).to_string()
+            }
+        },
+    }
+}
+
+pub fn get_status_response(client_version: i32, virtual_host: &str) -> StatusResponse {
+    let players = Players {
+        max: max_players(ProxyServer::instance().config().max_players),
+        online: ProxyServer::instance().player_count as i32,
+        sample: hover_sample(),
+    };
+    build_status_response(client_version, virtual_host, players)
+}
+
+fn build_status_response(
+    client_version: i32,
+    virtual_host: &str,
+    players: Players,
+) -> StatusResponse {
     StatusResponse {
+        description: Some(motd(client_version, virtual_host, &players)),
         version: Version {
             name: format!(
                 "{} {}",
@@ -17,24 +213,229 @@ pub fn get_status_response(client_version: i32) -> StatusResponse {
                 -1
             },
         },
-        players: Players {
-            max: ProxyServer::instance().config().max_players,
-            online: ProxyServer::instance().player_count as i32,
-            sample: None,
-        },
-        description: Some(ProxyServer::instance().config().motd.clone()),
+        players,
         favicon: ProxyServer::instance().favicon.clone(),
     }
 }
 
+fn max_players(max: i32) -> i32 {
+    ProxyServer::instance()
+        .config()
+        .status
+        .fake_max_players
+        .unwrap_or(max)
+}
+
+fn hover_sample() -> Option<Vec<Player>> {
+    let hover = &ProxyServer::instance().config().status.hover;
+    if hover.is_empty() {
+        return None;
+    }
+    Some(
+        hover
+            .iter()
+            .map(|line| Player {
+                name: line.replace('&', "§"),
+                id: Uuid::nil().to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Picks the motd for the client and fills in ``{online}``, ``{max}``, ``{version}`` and ``{status:<server>}``
+fn motd(client_version: i32, virtual_host: &str, players: &Players) -> Text {
+    let config = ProxyServer::instance().config();
+    let unsupported = config
+        .status
+        .unsupported_motd
+        .as_ref()
+        .filter(|_| !version::is_supported(client_version));
+    let motds = match config.forced_hosts.get(virtual_host) {
+        Some(host) if !host.motds.is_empty() => &host.motds,
+        _ => &config.status.motds,
+    };
+    let motd = unsupported
+        .or_else(|| motds.choose(&mut rand::thread_rng()))
+        .unwrap_or(&config.motd);
+    // plain strings use legacy colour codes, components can use hex colours
+    let motd = match motd {
+        Value::String(motd) => Value::String(motd.replace('&', "§")),
+        motd => motd.clone(),
+    };
+    let version_name = ALL_VERSION_INDICES
+        .get(&client_version)
+        .map_or(SUPPORTED_VERSION_RANGE, |index| ALL_VERSION_NAMES[*index]);
+    let json = replace_status_placeholders(
+        &motd
+            .to_string()
+            .replace("{online}", &players.online.to_string())
+            .replace("{max}", &players.max.to_string())
+            .replace("{version}", version_name),
+    );
+    match serde_json::from_str(&json)
+        .ok()
+        .and_then(|value| chat::deserialize_json(&value).ok())
+    {
+        Some(text) => text,
+        None => {
+            debug!("Invalid motd: {}", json);
+            Text::new(json)
+        }
+    }
+}
+
+/// Replaces ``{status:<server>}`` with online or offline as known from the health checks
+fn replace_status_placeholders(motd: &str) -> String {
+    let mut result = String::with_capacity(motd.len());
+    let mut rest = motd;
+    while let Some(start) = rest.find("{status:") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let server = &rest[start + "{status:".len()..end];
+        result.push_str(&rest[..start]);
+        result.push_str(match health::status(server) {
+            Some(status) if status.online => "§aonline§r",
+            Some(_) => "§coffline§r",
+            None => "§7unknown§r",
+        });
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+pub async fn get_aggregated_status_response(
+    client_version: i32,
+    virtual_host: &str,
+) -> StatusResponse {
+    let config = &ProxyServer::instance().config().status;
+    let mut players = Players {
+        max: ProxyServer::instance().config().max_players,
+        online: ProxyServer::instance().player_count as i32,
+        sample: None,
+    };
+    let labels = ProxyServer::instance()
+        .servers()
+        .read()
+        .await
+        .list_servers()
+        .map(|info| info.label.clone())
+        .collect::<Vec<_>>();
+    let statuses = labels
+        .into_iter()
+        .filter_map(|label| {
+            health::status(&label)
+                .filter(|status| status.online)
+                .map(|status| (label, status))
+        })
+        .collect::<Vec<_>>();
+    // without health checks there is nothing to aggregate
+    if !statuses.is_empty() {
+        players.online = statuses.iter().map(|(_, status)| status.players).sum();
+        if config.sum_max_players {
+            players.max = statuses.iter().map(|(_, status)| status.max_players).sum();
+        }
+    }
+    players.max = max_players(players.max);
+    players.sample = match config.sample {
+        _ if !config.hover.is_empty() => hover_sample(),
+        PlayerSample::Players => {
+            let players = ProxyServer::instance()
+                .player_by_name
+                .read()
+                .await
+                .values()
+                .filter_map(WeakHandle::upgrade)
+                .map(|player| Player {
+                    name: player.name.clone(),
+                    id: player.uuid.to_string(),
+                })
+                .collect::<Vec<_>>();
+            Some(
+                players
+                    .choose_multiple(&mut rand::thread_rng(), config.sample_size)
+                    .cloned()
+                    .collect(),
+            )
+        }
+        PlayerSample::Servers => Some(
+            statuses
+                .iter()
+                .take(config.sample_size)
+                .map(|(label, status)| Player {
+                    name: format!("{}: {}", label, status.players),
+                    id: Uuid::nil().to_string(),
+                })
+                .collect(),
+        ),
+        PlayerSample::None => None,
+    };
+    build_status_response(client_version, virtual_host, players)
+}
+
+/// Relays the status of the first healthy server of the forced host or the priorities
+async fn passthrough(client_version: i32, virtual_host: &str) -> IOResult<String> {
+    let config = &ProxyServer::instance().config().status;
+    let priorities = match ProxyServer::instance().config().forced_hosts.get(virtual_host) {
+        Some(host) if !host.priorities.is_empty() => host.priorities.clone(),
+        _ => ProxyServer::instance().servers().read().await.get_priorities().to_vec(),
+    };
+    let timeout = Duration::from_millis(config.passthrough_timeout);
+    for name in priorities {
+        let info = match server_group::resolve(&name).await {
+            Some(info) if health::is_healthy(&info.label) => info,
+            _ => continue,
+        };
+        let mut response = match tokio::time::timeout(
+            timeout,
+            health::request_status(&info.address, info.proxy_protocol, client_version),
+        )
+        .await
+        {
+            Ok(Ok((response, _))) => response,
+            Ok(Err(e)) => {
+                debug!("Status request to {} failed: {}", info.label, e);
+                continue;
+            }
+            Err(_) => {
+                debug!("Status request to {} timed out", info.label);
+                continue;
+            }
+        };
+        if config.proxy_favicon {
+            if let Some(ref favicon) = ProxyServer::instance().favicon {
+                response["favicon"] = Value::String(favicon.clone());
+            }
+        }
+        return Ok(response.to_string());
+    }
+    Err(IOError::new(
+        IOErrorKind::NotFound,
+        "No server answered the status request",
+    ))
+}
+
 #[derive(Debug, Serialize)]
 pub struct StatusResponse {
     pub version: Version,
     pub players: Players,
-    pub description: Option<String>,
+    #[serde(serialize_with = "serialize_description")]
+    pub description: Option<Text>,
     pub favicon: Option<String>,
 }
 
+fn serialize_description<S: Serializer>(
+    description: &Option<Text>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    description
+        .as_ref()
+        .map(chat::serialize_json)
+        .serialize(serializer)
+}
+
 impl Display for StatusResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", serde_json::to_string(self).unwrap())
@@ -54,7 +455,7 @@ pub struct Players {
     pub sample: Option<Vec<Player>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Player {
     pub name: String,
     pub id: String,
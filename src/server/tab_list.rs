@@ -0,0 +1,264 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::{
+    chat::Text,
+    util::{Handle, IOResult, WeakHandle},
+};
+
+use super::{
+    packets::{
+        self, PlayerInfoEntry, PlayerInfoRemove, PlayerInfoUpdate, ProtocolState,
+        TabListHeaderFooter, PLAYER_INFO_ADD_PLAYER, PLAYER_INFO_UPDATE_DISPLAY_NAME,
+        PLAYER_INFO_UPDATE_GAME_MODE, PLAYER_INFO_UPDATE_LATENCY, PLAYER_INFO_UPDATE_LISTED,
+    },
+    ProxiedPlayer, ProxyServer,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TabListConfig {
+    /// Shows every player on the proxy instead of only the players of the current server
+    #[serde(default)]
+    pub global: bool,
+    /// Display name of players on other servers, supports ``{player}``, ``{server}`` and ``{ping}``
+    #[serde(default = "default_player_format")]
+    pub player_format: String,
+    /// Supports ``{player}``, ``{server}``, ``{ping}``, ``{online}`` and ``{max}``, empty to leave it to the backend
+    #[serde(default)]
+    pub header: String,
+    #[serde(default)]
+    pub footer: String,
+    /// Milliseconds between updates of ping and header/footer
+    #[serde(default = "default_update_interval")]
+    pub update_interval: u64,
+}
+
+fn default_player_format() -> String {
+    "{player} &7[{server}]".to_owned()
+}
+
+fn default_update_interval() -> u64 {
+    2000
+}
+
+impl Default for TabListConfig {
+    fn default() -> Self {
+        Self {
+            global: false,
+            player_format: default_player_format(),
+            header: String::new(),
+            footer: String::new(),
+            update_interval: default_update_interval(),
+        }
+    }
+}
+
+impl TabListConfig {
+    pub fn has_header_footer(&self) -> bool {
+        !self.header.is_empty() || !self.footer.is_empty()
+    }
+}
+
+/// Entries of the client tab list, cleared when the client goes back to the config state
+#[derive(Default)]
+pub struct TabListTracker {
+    pub backend_entries: HashSet<Uuid>,
+    /// Entries added by the global tab list with the server and latency they were sent with
+    pub proxy_entries: HashMap<Uuid, (String, i32)>,
+}
+
+impl TabListTracker {
+    pub fn on_update(&mut self, packet: &PlayerInfoUpdate) {
+        if packet.actions & PLAYER_INFO_ADD_PLAYER != 0 {
+            for entry in &packet.entries {
+                self.proxy_entries.remove(&entry.uuid);
+                self.backend_entries.insert(entry.uuid);
+            }
+        }
+    }
+
+    pub fn on_remove(&mut self, packet: &PlayerInfoRemove) {
+        for uuid in &packet.uuids {
+            self.backend_entries.remove(uuid);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.backend_entries.clear();
+        self.proxy_entries.clear();
+    }
+}
+
+lazy_static! {
+    static ref UPDATE_NOTIFY: Notify = Notify::new();
+}
+
+/// Updates all tab lists without waiting for the next interval
+pub fn request_update() {
+    UPDATE_NOTIFY.notify_one();
+}
+
+pub fn start() {
+    let config = &ProxyServer::instance().config().tab_list;
+    if !config.global && !config.has_header_footer() {
+        return;
+    }
+    let interval = Duration::from_millis(config.update_interval.max(100));
+    ProxyServer::instance().spawn_task(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = UPDATE_NOTIFY.notified() => {}
+            }
+            update_all().await;
+        }
+    });
+}
+
+fn replace_placeholders(template: &str, player: &ProxiedPlayer) -> String {
+    template
+        .replace('&', "§")
+        .replace("{player}", &player.name)
+        .replace("{server}", player.current_server.as_deref().unwrap_or(""))
+        .replace(
+            "{ping}",
+            &player.sync_data.ping.load(Ordering::Relaxed).to_string(),
+        )
+}
+
+async fn update_all() {
+    let proxy_server = ProxyServer::instance();
+    let config = &proxy_server.config().tab_list;
+    let players = proxy_server
+        .player_by_name
+        .read()
+        .await
+        .values()
+        .filter_map(WeakHandle::upgrade)
+        .collect::<Vec<_>>();
+    let online = players.len().to_string();
+    let max = proxy_server.config().max_players.to_string();
+
+    for player in &players {
+        if player.client_handle.protocol_state() != ProtocolState::Game
+            || *player.sync_data.is_switching_server.lock().await
+        {
+            continue;
+        }
+        if config.global {
+            if let Err(e) = update_entries(player, &players).await {
+                log::debug!("[{}] Failed to update tab list: {}", player.name, e);
+            }
+        }
+        if config.has_header_footer() {
+            let packet = TabListHeaderFooter {
+                header: Text::new(
+                    replace_placeholders(&config.header, player)
+                        .replace("{online}", &online)
+                        .replace("{max}", &max),
+                ),
+                footer: Text::new(
+                    replace_placeholders(&config.footer, player)
+                        .replace("{online}", &online)
+                        .replace("{max}", &max),
+                ),
+            };
+            if let Ok(Some(data)) = packets::get_full_server_packet_buf(
+                &packet,
+                player.protocol_version,
+                ProtocolState::Game,
+            ) {
+                player.client_handle.queue_packet(data, false).await.ok();
+            }
+        }
+    }
+}
+
+/// Adds the players the backend doesn't know about and removes the ones that left the proxy
+async fn update_entries(player: &Handle<ProxiedPlayer>, players: &[Handle<ProxiedPlayer>]) -> IOResult<()> {
+    let format = &ProxyServer::instance().config().tab_list.player_format;
+    let mut tracker = player.sync_data.tab_list.lock().await;
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    let mut online = HashSet::new();
+    for other in players {
+        let server = match other.current_server {
+            Some(ref server) => server.clone(),
+            None => continue,
+        };
+        if other.client_handle.closed.load(Ordering::Relaxed)
+            || tracker.backend_entries.contains(&other.uuid)
+        {
+            continue;
+        }
+        online.insert(other.uuid);
+        let latency = other.sync_data.ping.load(Ordering::Relaxed);
+        let entry = PlayerInfoEntry {
+            uuid: other.uuid,
+            name: other.name.clone(),
+            properties: other.login_result.properties.clone(),
+            listed: true,
+            latency,
+            display_name: Some(Text::new(replace_placeholders(format, other))),
+            ..Default::default()
+        };
+        match tracker.proxy_entries.get(&other.uuid) {
+            Some((s, l)) if *s == server && *l == latency => continue,
+            Some(_) => updated.push(entry),
+            None => added.push(entry),
+        }
+        tracker.proxy_entries.insert(other.uuid, (server, latency));
+    }
+    let removed = tracker
+        .proxy_entries
+        .keys()
+        .filter(|uuid| !online.contains(uuid))
+        .copied()
+        .collect::<Vec<_>>();
+    for uuid in &removed {
+        tracker.proxy_entries.remove(uuid);
+    }
+    drop(tracker);
+
+    let version = player.protocol_version;
+    if !removed.is_empty() {
+        if let Some(data) = packets::get_full_server_packet_buf(
+            &PlayerInfoRemove { uuids: removed },
+            version,
+            ProtocolState::Game,
+        )? {
+            player.client_handle.queue_packet(data, false).await?;
+        }
+    }
+    if !added.is_empty() {
+        let packet = PlayerInfoUpdate {
+            actions: PLAYER_INFO_ADD_PLAYER
+                | PLAYER_INFO_UPDATE_GAME_MODE
+                | PLAYER_INFO_UPDATE_LISTED
+                | PLAYER_INFO_UPDATE_LATENCY
+                | PLAYER_INFO_UPDATE_DISPLAY_NAME,
+            entries: added,
+        };
+        if let Some(data) = packets::get_full_server_packet_buf(&packet, version, ProtocolState::Game)? {
+            player.client_handle.queue_packet(data, false).await?;
+        }
+    }
+    if !updated.is_empty() {
+        let packet = PlayerInfoUpdate {
+            actions: PLAYER_INFO_UPDATE_LATENCY | PLAYER_INFO_UPDATE_DISPLAY_NAME,
+            entries: updated,
+        };
+        if let Some(data) = packets::get_full_server_packet_buf(&packet, version, ProtocolState::Game)? {
+            player.client_handle.queue_packet(data, false).await?;
+        }
+    }
+    Ok(())
+}
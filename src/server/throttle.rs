@@ -0,0 +1,105 @@
+use std::net::IpAddr;
+
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    rate_limit::{self, IpRateLimiter, RateLimitConfig},
+    ProxyConfig, ProxyServer,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionThrottleConfig {
+    /// Logins per address
+    #[serde(default = "default_per_ip")]
+    pub per_ip: RateLimitConfig,
+    /// Logins per /24 or /48 subnet, shared by everyone behind the same NAT
+    #[serde(default = "default_per_subnet")]
+    pub per_subnet: RateLimitConfig,
+    /// Addresses or subnets like ``10.0.0.0/8`` that are never throttled
+    #[serde(default)]
+    pub exempt: Vec<String>,
+    /// Sent to throttled logins, empty to close the connection without a message
+    #[serde(default = "default_kick_message")]
+    pub kick_message: String,
+}
+
+fn default_per_ip() -> RateLimitConfig {
+    RateLimitConfig::new(3, 0.2)
+}
+
+fn default_per_subnet() -> RateLimitConfig {
+    RateLimitConfig {
+        ipv4_prefix: 24,
+        ipv6_prefix: 48,
+        ..RateLimitConfig::new(20, 2.0)
+    }
+}
+
+fn default_kick_message() -> String {
+    "&cYou are logging in too fast, please wait a moment".to_owned()
+}
+
+impl Default for ConnectionThrottleConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_per_ip(),
+            per_subnet: default_per_subnet(),
+            exempt: Vec::new(),
+            kick_message: default_kick_message(),
+        }
+    }
+}
+
+/// Maps the keys of the old fixed window throttle onto the per address bucket, a time of 0 disables throttling
+pub fn apply_legacy_config(config: &mut ProxyConfig) {
+    if config.connection_throttle_time.is_none() && config.connection_throttle_limit.is_none() {
+        return;
+    }
+    warn!("connection_throttle_time and connection_throttle_limit are deprecated, use connection_throttle instead");
+    let time = config.connection_throttle_time.unwrap_or(5000);
+    let limit = config.connection_throttle_limit.unwrap_or(20);
+    let throttle = &mut config.connection_throttle;
+    if time <= 0 {
+        throttle.per_ip.enabled = false;
+        throttle.per_subnet.enabled = false;
+    } else {
+        throttle.per_ip = RateLimitConfig::new(limit.into(), limit as f64 * 1000.0 / time as f64);
+    }
+}
+
+lazy_static! {
+    static ref PER_IP: IpRateLimiter =
+        IpRateLimiter::new(&ProxyServer::instance().config().connection_throttle.per_ip);
+    static ref PER_SUBNET: IpRateLimiter = IpRateLimiter::new(
+        &ProxyServer::instance()
+            .config()
+            .connection_throttle
+            .per_subnet
+    );
+    static ref EXEMPT: Vec<(IpAddr, u8)> = ProxyServer::instance()
+        .config()
+        .connection_throttle
+        .exempt
+        .iter()
+        .filter_map(|cidr| {
+            let subnet = rate_limit::parse_cidr(cidr);
+            if subnet.is_none() {
+                warn!("Invalid exempt address {}", cidr);
+            }
+            subnet
+        })
+        .collect();
+}
+
+pub fn is_exempt(ip: IpAddr) -> bool {
+    EXEMPT
+        .iter()
+        .any(|(subnet, prefix)| rate_limit::subnet(ip, *prefix, *prefix) == *subnet)
+}
+
+/// Takes a token from the login buckets of the address and its subnet
+pub fn check_login(ip: IpAddr) -> bool {
+    is_exempt(ip) || (PER_IP.check(ip) && PER_SUBNET.check(ip))
+}
@@ -277,6 +277,12 @@ impl EncodingHelper {
     }
 
     pub fn read_text<R: Read + ?Sized>(src: &mut R, version: i32) -> IOResult<Text> {
+        if version < crate::version::R1_20_3 {
+            let string = Self::read_string(src, 262144)?;
+            let json = serde_json::from_str(&string)?;
+            return crate::chat::deserialize_json(&json)
+                .map_err(|err| IOError::new(IOErrorKind::InvalidData, err));
+        }
         let nbt = crate::server::nbt::read_networking_nbt(src, version)?;
         if let Some(nbt_tag) = nbt.left() {
             if let Some(nbt_tag) = nbt_tag {
@@ -293,6 +299,9 @@ impl EncodingHelper {
 
     pub fn write_text<W: Write + ?Sized>(dest: &mut W, version: i32, text: &Text) -> IOResult<()> {
         let json = crate::chat::serialize_json(text);
+        if version < crate::version::R1_20_3 {
+            return Self::write_string(dest, &serde_json::to_string(&json)?);
+        }
         let nbt = NbtType::from_json(&json)?;
         crate::server::nbt::write_networking_nbt(dest, version, &Either::Left(Some(nbt)))?;
         Ok(())